@@ -0,0 +1,309 @@
+// Copyright 2015-2020 Parity Technologies (UK) Ltd.
+// This file is part of OpenEthereum.
+
+// OpenEthereum is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// OpenEthereum is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with OpenEthereum.  If not, see <http://www.gnu.org/licenses/>.
+
+//! CBOR (RFC 7049) encoding of instrumentation events.
+//!
+//! Each event becomes one definite-length CBOR map: the `type` key carries
+//! the lowercased event name, followed by the fields in their declared
+//! order, with a repeated field name collapsed into one array entry. The
+//! subset of CBOR used is deliberately small — unsigned and negative
+//! integers, byte and text strings, arrays, booleans, null and the tag-2
+//! bignum for 256-bit quantities — so any off-the-shelf decoder handles it.
+
+use event::FieldValue;
+
+/// CBOR major types, shifted into the high three bits of the initial byte.
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_SIMPLE: u8 = 7;
+
+/// Tag marking the following byte string as a big-endian unsigned bignum.
+const TAG_BIGNUM: u64 = 2;
+
+/// Writes the initial byte of a CBOR item: the major type with its argument
+/// packed directly when below 24, through the 1/2/4/8-byte follow-on
+/// encodings otherwise.
+fn write_header(out: &mut Vec<u8>, major: u8, argument: u64) {
+    let major = major << 5;
+    if argument < 24 {
+        out.push(major | argument as u8);
+    } else if argument <= 0xff {
+        out.push(major | 24);
+        out.push(argument as u8);
+    } else if argument <= 0xffff {
+        out.push(major | 25);
+        out.extend_from_slice(&(argument as u16).to_be_bytes());
+    } else if argument <= 0xffff_ffff {
+        out.push(major | 26);
+        out.extend_from_slice(&(argument as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&argument.to_be_bytes());
+    }
+}
+
+fn write_text(out: &mut Vec<u8>, text: &str) {
+    write_header(out, MAJOR_TEXT, text.len() as u64);
+    out.extend_from_slice(text.as_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_header(out, MAJOR_BYTES, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn write_value(out: &mut Vec<u8>, value: &FieldValue) {
+    match *value {
+        FieldValue::U64(v) | FieldValue::Gas(v) => write_header(out, MAJOR_UNSIGNED, v),
+        FieldValue::I64(v) => {
+            if v >= 0 {
+                write_header(out, MAJOR_UNSIGNED, v as u64);
+            } else {
+                // CBOR encodes a negative n as the unsigned -1 - n.
+                write_header(out, MAJOR_NEGATIVE, !(v as u64));
+            }
+        }
+        FieldValue::U256(ref v) => {
+            // Tag-2 bignum over the minimal big-endian magnitude, so small
+            // values stay small and zero is the empty byte string.
+            write_header(out, 6, TAG_BIGNUM);
+            let mut raw = [0u8; 32];
+            v.to_big_endian(&mut raw);
+            let start = raw.iter().position(|b| *b != 0).unwrap_or(raw.len());
+            write_bytes(out, &raw[start..]);
+        }
+        FieldValue::Address(ref v) => write_bytes(out, v.as_bytes()),
+        FieldValue::H256(ref v) => write_bytes(out, v.as_bytes()),
+        FieldValue::Bytes(ref v) => write_bytes(out, v),
+        FieldValue::Str(ref v) => write_text(out, v),
+        FieldValue::Bool(v) => out.push((MAJOR_SIMPLE << 5) | if v { 21 } else { 20 }),
+        FieldValue::Null => out.push((MAJOR_SIMPLE << 5) | 22),
+    }
+}
+
+/// Encodes one event as a definite-length CBOR map. A field name repeated
+/// on the event (topics, uncle hashes, slot pairs) becomes one
+/// definite-length array under that key, at the position of its first
+/// occurrence — RFC 7049 §3.7 makes maps with duplicate keys invalid, and
+/// strict decoders reject them.
+pub(crate) fn encode(name: &str, fields: &[(&'static str, FieldValue)]) -> Vec<u8> {
+    let mut keys: Vec<&str> = Vec::new();
+    for &(name, _) in fields {
+        if !keys.contains(&name) {
+            keys.push(name);
+        }
+    }
+    let mut out = Vec::new();
+    write_header(&mut out, MAJOR_MAP, keys.len() as u64 + 1);
+    write_text(&mut out, "type");
+    write_text(&mut out, &name.to_lowercase());
+    for key in keys {
+        let values: Vec<&FieldValue> = fields
+            .iter()
+            .filter(|&&(name, _)| name == key)
+            .map(|&(_, ref value)| value)
+            .collect();
+        write_text(&mut out, key);
+        if values.len() == 1 {
+            write_value(&mut out, values[0]);
+        } else {
+            write_header(&mut out, MAJOR_ARRAY, values.len() as u64);
+            for value in values {
+                write_value(&mut out, value);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eth::{Address, H256, U256};
+    use event::Event;
+
+    /// A decoded CBOR item, covering exactly the subset the encoder emits.
+    #[derive(Debug, PartialEq)]
+    enum Item {
+        Unsigned(u64),
+        Negative(i64),
+        Bytes(Vec<u8>),
+        Text(String),
+        Array(Vec<Item>),
+        Bignum(Vec<u8>),
+        Bool(bool),
+        Null,
+    }
+
+    /// Minimal independent decoder: reads one item at `pos`, advancing it.
+    /// Any deviation from the encoded subset panics the test.
+    fn decode(data: &[u8], pos: &mut usize) -> Item {
+        let initial = data[*pos];
+        *pos += 1;
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let argument = match info {
+            n if n < 24 => u64::from(n),
+            24..=27 => {
+                let len = 1usize << (info - 24);
+                let mut v = 0u64;
+                for _ in 0..len {
+                    v = v << 8 | u64::from(data[*pos]);
+                    *pos += 1;
+                }
+                v
+            }
+            _ => panic!("indefinite lengths are never emitted"),
+        };
+        match major {
+            0 => Item::Unsigned(argument),
+            1 => Item::Negative(-1 - argument as i64),
+            2 | 3 => {
+                let raw = data[*pos..*pos + argument as usize].to_vec();
+                *pos += argument as usize;
+                if major == 2 {
+                    Item::Bytes(raw)
+                } else {
+                    Item::Text(String::from_utf8(raw).unwrap())
+                }
+            }
+            4 => Item::Array((0..argument).map(|_| decode(data, pos)).collect()),
+            6 => {
+                assert_eq!(argument, TAG_BIGNUM);
+                match decode(data, pos) {
+                    Item::Bytes(raw) => Item::Bignum(raw),
+                    other => panic!("bignum tag over {:?}", other),
+                }
+            }
+            7 => match argument {
+                20 => Item::Bool(false),
+                21 => Item::Bool(true),
+                22 => Item::Null,
+                other => panic!("unexpected simple value {}", other),
+            },
+            other => panic!("unexpected major type {}", other),
+        }
+    }
+
+    fn decode_map(data: &[u8]) -> Vec<(String, Item)> {
+        let mut pos = 0;
+        let initial = data[pos];
+        pos += 1;
+        assert_eq!(initial >> 5, MAJOR_MAP);
+        let entries = u64::from(initial & 0x1f);
+        let mut map: Vec<(String, Item)> = Vec::new();
+        for _ in 0..entries {
+            let key = match decode(data, &mut pos) {
+                Item::Text(key) => key,
+                other => panic!("non-text key {:?}", other),
+            };
+            assert!(
+                map.iter().all(|&(ref seen, _)| *seen != key),
+                "duplicate map key {:?}",
+                key
+            );
+            map.push((key, decode(data, &mut pos)));
+        }
+        assert_eq!(pos, data.len());
+        map
+    }
+
+    #[test]
+    fn events_round_trip_through_cbor() {
+        let address = Address::from_low_u64_be(0xc0de);
+        let event = Event::new("BALANCE_CHANGE")
+            .u64("call_index", 3)
+            .address("address", &address)
+            .u256("old", &U256::zero())
+            .u256("new", &U256::from(0x1234_5678u64))
+            .i64("refund_delta", -15000)
+            .h256("key", &H256::from_low_u64_be(1))
+            .bytes("data", &[0xab, 0xcd])
+            .string("reason", "transfer")
+            .bool("cold", true)
+            .opt_u64("chain_id", None);
+
+        let encoded = encode(event.name(), event.fields());
+        assert_eq!(
+            decode_map(&encoded),
+            vec![
+                ("type".to_owned(), Item::Text("balance_change".to_owned())),
+                ("call_index".to_owned(), Item::Unsigned(3)),
+                ("address".to_owned(), Item::Bytes(address.as_bytes().to_vec())),
+                ("old".to_owned(), Item::Bignum(Vec::new())),
+                ("new".to_owned(), Item::Bignum(vec![0x12, 0x34, 0x56, 0x78])),
+                ("refund_delta".to_owned(), Item::Negative(-15000)),
+                (
+                    "key".to_owned(),
+                    Item::Bytes(H256::from_low_u64_be(1).as_bytes().to_vec()),
+                ),
+                ("data".to_owned(), Item::Bytes(vec![0xab, 0xcd])),
+                ("reason".to_owned(), Item::Text("transfer".to_owned())),
+                ("cold".to_owned(), Item::Bool(true)),
+                ("chain_id".to_owned(), Item::Null),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_fields_encode_as_one_array_entry() {
+        let topic_a = H256::from_low_u64_be(0xaa);
+        let topic_b = H256::from_low_u64_be(0xbb);
+        let event = Event::new("ADD_LOG")
+            .u64("call_index", 1)
+            .h256("topic", &topic_a)
+            .h256("topic", &topic_b);
+
+        let encoded = encode(event.name(), event.fields());
+        assert_eq!(
+            decode_map(&encoded),
+            vec![
+                ("type".to_owned(), Item::Text("add_log".to_owned())),
+                ("call_index".to_owned(), Item::Unsigned(1)),
+                (
+                    "topic".to_owned(),
+                    Item::Array(vec![
+                        Item::Bytes(topic_a.as_bytes().to_vec()),
+                        Item::Bytes(topic_b.as_bytes().to_vec()),
+                    ]),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn integer_arguments_use_the_shortest_encoding() {
+        for &(value, expected_len) in &[
+            (23u64, 1usize),
+            (24, 2),
+            (0xff, 2),
+            (0x100, 3),
+            (0xffff, 3),
+            (0x1_0000, 5),
+            (0xffff_ffff, 5),
+            (0x1_0000_0000, 9),
+        ] {
+            let mut out = Vec::new();
+            write_header(&mut out, MAJOR_UNSIGNED, value);
+            assert_eq!(out.len(), expected_len, "for {}", value);
+            let mut pos = 0;
+            assert_eq!(decode(&out, &mut pos), Item::Unsigned(value));
+        }
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright 2015-2020 Parity Technologies (UK) Ltd.
+// This file is part of OpenEthereum.
+
+// OpenEthereum is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// OpenEthereum is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with OpenEthereum.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Instrumentation stream configuration.
+
+use eth;
+
+/// Encoding used for emitted event lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Positional space-delimited fields: `EVENT field0 field1 ...`.
+    Text,
+    /// One JSON object per line: `{"type":"event","field0":...}`.
+    Json,
+    /// One CBOR map per line, hex encoded with a decimal byte-length
+    /// prefix: `<len> <hex>`. Requires the `cbor` feature.
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Default for Format {
+    fn default() -> Format {
+        Format::Text
+    }
+}
+
+/// How much uncle data the `UNCLES` block event carries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UncleDetail {
+    /// Uncle count followed by every uncle header, RLP encoded.
+    Full,
+    /// Uncle count only, for consumers doing pure reward accounting.
+    CountOnly,
+    /// No uncle data at all.
+    None,
+}
+
+impl Default for UncleDetail {
+    fn default() -> UncleDetail {
+        UncleDetail::Full
+    }
+}
+
+/// How much of the block's touched-address summary the `BLOCK_ADDRESSES`
+/// event carries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockAddressDetail {
+    /// Address count followed by every touched address, sorted.
+    Full,
+    /// Address count only, bounding the line size on busy blocks.
+    CountOnly,
+    /// No summary at all.
+    None,
+}
+
+impl Default for BlockAddressDetail {
+    fn default() -> BlockAddressDetail {
+        BlockAddressDetail::CountOnly
+    }
+}
+
+/// Delimiter placed between fields in text mode. Defaults to a space, the
+/// historical convention of the stream; operators can pick a character
+/// guaranteed absent from every field (e.g. tab, which no hex, decimal or
+/// reason string contains) to keep lines unambiguously splittable should a
+/// future string field ever carry a space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldDelimiter(pub char);
+
+impl Default for FieldDelimiter {
+    fn default() -> FieldDelimiter {
+        FieldDelimiter(' ')
+    }
+}
+
+/// How zero values and empty byte strings render in text mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmptySentinel {
+    /// The `.` sentinel, the historical convention of the stream.
+    Dot,
+    /// An empty string. Note that this produces consecutive delimiters,
+    /// which simple `split` based consumers must be prepared for.
+    Empty,
+    /// `0x0` for zero numeric values and `0x` for empty bytes.
+    ZeroHex,
+}
+
+impl Default for EmptySentinel {
+    fn default() -> EmptySentinel {
+        EmptySentinel::Dot
+    }
+}
+
+/// Configuration of the instrumentation stream.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Encoding used for emitted event lines.
+    pub format: Format,
+    /// When enabled, every emitted event is followed by a `TIMING` line on
+    /// the `DMDEBUG` channel carrying the nanoseconds elapsed since
+    /// `start_block`, for micro-profiling the EVM.
+    ///
+    /// Reading the monotonic clock costs one `clock_gettime` call per event
+    /// (some tens of nanoseconds through the vDSO on Linux), which is
+    /// measurable on busy blocks; leave this off in production.
+    pub timing: bool,
+    /// When enabled, opcode-level introspection events are emitted, such
+    /// as `GAS_OPCODE` carrying the remaining gas a contract observed
+    /// through the GAS opcode. Off by default: introspection opcodes can
+    /// run in tight loops and the events add nothing to state
+    /// reconstruction, only to gas-dependent control flow analysis.
+    pub opcode_level: bool,
+    /// When enabled, `DMDEBUG` lines carry the Rust source location
+    /// (`file:line`) of the `record_*` call site that produced them,
+    /// captured through `#[track_caller]`, so a bogus event can be traced
+    /// back to where the instrumentation emitted it. Strictly a developer
+    /// aid: protocol lines on `DMLOG` are never tagged.
+    pub debug_source_locations: bool,
+    /// When enabled, gas quantities (`gas_limit`, `gas_left`, `gas_used`,
+    /// gas costs) are encoded as quoted decimal strings in JSON mode, so
+    /// consumers whose JSON parsers read numbers as 64-bit floats
+    /// (e.g. JavaScript) do not lose precision. Has no effect in text mode.
+    pub gas_as_string: bool,
+    /// How much uncle data the `UNCLES` block event carries.
+    pub uncle_detail: UncleDetail,
+    /// How much of the block's touched-address summary the
+    /// `BLOCK_ADDRESSES` event at `end_block` carries. Every address with
+    /// any state change — balance, storage, code, self-destruction —
+    /// counts as touched; blocks touching nothing stay silent.
+    pub block_address_detail: BlockAddressDetail,
+    /// How zero values and empty byte strings render in text mode.
+    pub empty_sentinel: EmptySentinel,
+    /// Delimiter placed between fields in text mode.
+    pub field_delimiter: FieldDelimiter,
+    /// When enabled, 256-bit values are zero-padded to their full 64-char
+    /// hex width (addresses are always 40 chars) instead of trimmed to
+    /// their natural width, and zero values render as full-width zeros
+    /// rather than the empty sentinel, so consumers can parse fields at
+    /// fixed offsets.
+    pub fixed_width_hex: bool,
+    /// When enabled, a `CHAIN_SPEC` line carrying a JSON manifest of the
+    /// chain spec (chain id, fork activation blocks, precompile addresses)
+    /// is emitted once at startup, making the stream self-describing for
+    /// replay without access to the node's spec file.
+    pub chain_spec_manifest: bool,
+    /// When enabled, the header's `logs_bloom` is cross-checked at
+    /// `end_block` against the OR of all transaction logs blooms, with a
+    /// `BLOOM_MISMATCH` warning on the `DMDEBUG` channel when they differ.
+    /// Off by default: ORing 256-byte blooms for every block is pure
+    /// verification overhead for consumers that trust the header.
+    pub verify_bloom: bool,
+    /// When enabled, `GAS_CHANGE` events carry a trailing tag naming the
+    /// fork whose schedule priced them (e.g. `berlin`), so consumers need
+    /// not cross-reference block numbers against fork activations to
+    /// interpret a gas reason. Off by default to keep lines compact.
+    pub fork_tags: bool,
+    /// When enabled, a `FINALITY` marker is emitted when the node's
+    /// finality signal reports a block as finalized, letting reorg-averse
+    /// consumers defer processing until then. Only meaningful on chains
+    /// with a finality gadget; off by default since most consumers follow
+    /// the head optimistically.
+    pub finality_markers: bool,
+    /// When enabled, a `TRANSACTIONS_LIST` line carrying the ordered
+    /// transaction hashes of the block is emitted, letting light-client
+    /// style consumers rebuild the transactions trie and check it against
+    /// the header's `transactions_root`. Off by default: the hashes all
+    /// appear individually on `BEGIN_APPLY_TRX` anyway.
+    pub transactions_list: bool,
+    /// When enabled, every event of a transaction carries a trailing scope
+    /// id derived from the transaction hash, so consumers can shard events
+    /// by transaction across workers without tracking
+    /// `BEGIN_APPLY_TRX`/`END_APPLY_TRX` framing.
+    pub transaction_scope_ids: bool,
+    /// When set, `BALANCE_CHANGE` events whose reason is a plain value
+    /// transfer and whose moved amount is below this many wei are
+    /// suppressed, for analytics pipelines that ignore dust. Only the
+    /// emission is filtered: the change still feeds the block's
+    /// touched-address summary and the poststate diff, and every other
+    /// reason (rewards, gas accounting, self-destructs) always emits.
+    /// `None`, the default, emits everything.
+    pub min_value_wei: Option<eth::U256>,
+    /// When enabled, the tracer additionally assembles the call frames of
+    /// every transaction into a nested call tree and emits it as one
+    /// `CALL_TRACE` line carrying a JSON object in the shape of Geth's
+    /// `callTracer` (`type`, `from`, `to`, `value`, `gas`, `gasUsed`,
+    /// `input`, `output`, `calls`), so existing Geth-trace tooling can
+    /// consume the stream directly. Off by default: the tree duplicates
+    /// the `EVM_RUN_CALL`/`EVM_END_CALL` events and buffers every frame.
+    pub call_tracer: bool,
+    /// When enabled, the tracer buffers every event of a transaction and
+    /// emits them grouped by call index when the transaction ends, instead
+    /// of streaming them in execution order. The relative order of events
+    /// within one call index is preserved. Costs one owned copy of every
+    /// event per transaction.
+    pub sort_by_call_index: bool,
+    /// When enabled, a `PRESTATE` snapshot of every touched account is
+    /// emitted at transaction start, enabling prestate/diff tracing from
+    /// the stream. Off by default: capturing balances, nonces, code hashes
+    /// and storage for every touched account is expensive.
+    pub prestate: bool,
+    /// When enabled, the full storage read and write sets of every
+    /// transaction are emitted as `READ_SET`/`WRITE_SET` lines when it
+    /// ends, so parallel-execution research can detect conflicts between
+    /// transactions. Off by default: the sets can be large and most
+    /// consumers only need the `STORAGE_STATS` counts.
+    pub access_sets: bool,
+    /// When enabled, a `POSTSTATE` diff of every changed account — final
+    /// balances and storage values, with changes undone by reverted frames
+    /// excluded — is emitted when the transaction ends. Together with
+    /// [`Config::prestate`] this reproduces the prestate/diff mode of
+    /// `debug_traceTransaction` from the stream.
+    pub poststate: bool,
+    /// When enabled, a call whose target already appears in the active call
+    /// path is flagged with a `REENTRANCY_DETECTED` marker. Purely a
+    /// heuristic hint for security analysis — re-entering an address is
+    /// legal and not necessarily an exploit.
+    pub reentrancy_hints: bool,
+    /// When enabled, JSON events are wrapped in a schema-versioned envelope
+    /// (`{"v":2,"type":...}`), letting individual events evolve without a
+    /// protocol major bump; consumers dispatch on `v`. Has no effect in text
+    /// mode.
+    pub schema_envelope: bool,
+}
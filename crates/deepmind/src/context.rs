@@ -0,0 +1,1334 @@
+// Copyright 2015-2020 Parity Technologies (UK) Ltd.
+// This file is part of OpenEthereum.
+
+// OpenEthereum is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// OpenEthereum is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with OpenEthereum.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Stream-level and block-level instrumentation contexts.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    sync::Arc,
+    time::Instant,
+};
+
+use parking_lot::Mutex;
+
+use config::{BlockAddressDetail, Config, UncleDetail};
+use eth;
+use event::{json_escape, Event, EventCategory};
+use printer::{Channel, Printer};
+use tracer::TransactionTracer;
+
+/// Monotonic time source used for event timing, overridable so tests can
+/// drive a deterministic clock.
+pub type Clock = Box<dyn Fn() -> Instant + Send + Sync>;
+
+/// Version of the instrumentation protocol, negotiated with the consumer
+/// through the `INIT` handshake.
+pub const PROTOCOL_VERSION: &'static str = "1.0";
+
+/// Maximum number of discarded block hashes carried by a `REORG` event.
+/// Deeper reorgs fall back to the depth alone, keeping the line bounded.
+const REORG_HASH_CAP: usize = 64;
+
+/// The subset of the chain spec carried by the `CHAIN_SPEC` manifest,
+/// filled in by the caller from the node's parsed spec.
+pub struct ChainSpec {
+    /// Chain name, e.g. `foundation`.
+    pub name: String,
+    /// EIP-155 chain id.
+    pub chain_id: u64,
+    /// Fork activation blocks as `(fork name, block number)` pairs, in
+    /// activation order.
+    pub forks: Vec<(String, u64)>,
+    /// Addresses of the builtin (precompile) contracts.
+    pub precompiles: Vec<eth::Address>,
+}
+
+/// Counters accumulated over the whole lifetime of a [`Context`], reported
+/// by the `STREAM_STATS` line at shutdown.
+#[derive(Default)]
+struct StreamStats {
+    blocks: u64,
+    transactions: u64,
+    events: u64,
+    bytes: u64,
+}
+
+/// Root of the instrumentation stream. Owns the output configuration and the
+/// printer shared by all block and transaction level tracers.
+pub struct Context {
+    config: Config,
+    printer: Arc<dyn Printer>,
+    routes: Mutex<Vec<(EventCategory, Arc<dyn Printer>)>>,
+    clock: Clock,
+    block_start: Mutex<Option<Instant>>,
+    stats: Mutex<StreamStats>,
+}
+
+impl Context {
+    /// Creates a context emitting through `printer`.
+    pub fn new(config: Config, printer: Arc<dyn Printer>) -> Arc<Context> {
+        Context::with_clock(config, printer, Box::new(Instant::now))
+    }
+
+    /// Creates a context with an explicit monotonic clock, used by tests to
+    /// make `TIMING` lines deterministic.
+    pub fn with_clock(config: Config, printer: Arc<dyn Printer>, clock: Clock) -> Arc<Context> {
+        Arc::new(Context {
+            config: config,
+            printer: printer,
+            routes: Mutex::new(Vec::new()),
+            clock: clock,
+            block_start: Mutex::new(None),
+            stats: Mutex::new(StreamStats::default()),
+        })
+    }
+
+    /// The output configuration of this stream.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Routes every event of `category` to `sink` instead of the default
+    /// printer, so consumers can process categories in parallel pipelines
+    /// (e.g. balance changes in one, storage changes in another). The last
+    /// route installed for a category wins. Install routes before the
+    /// stream starts.
+    pub fn route(&self, category: EventCategory, sink: Arc<dyn Printer>) {
+        self.routes.lock().push((category, sink));
+    }
+
+    /// The sink `event` is printed to: its category's route when one is
+    /// installed, the default printer otherwise.
+    fn sink_for(&self, event: &Event) -> Arc<dyn Printer> {
+        let category = event.category();
+        self.routes
+            .lock()
+            .iter()
+            .rev()
+            .find(|&&(routed, _)| routed == category)
+            .map(|&(_, ref sink)| sink.clone())
+            .unwrap_or_else(|| self.printer.clone())
+    }
+
+    /// Emits the `INIT` handshake identifying the protocol version and the
+    /// producing client. Must be the first line of the stream.
+    pub fn init(&self) {
+        self.emit(
+            Event::new("INIT")
+                .string("version", PROTOCOL_VERSION)
+                .string("client", "openethereum"),
+        );
+    }
+
+    /// Emits the `CHAIN_SPEC` manifest right after the `INIT` handshake.
+    /// The payload is a single JSON object regardless of the configured
+    /// format, since it is structured metadata rather than a stream event.
+    /// Does nothing unless `Config::chain_spec_manifest` is enabled.
+    pub fn record_chain_spec(&self, spec: &ChainSpec) {
+        if !self.config.chain_spec_manifest {
+            return;
+        }
+        let mut json = format!(
+            "{{\"name\":{},\"chain_id\":{},\"forks\":{{",
+            json_escape(&spec.name),
+            spec.chain_id
+        );
+        for (i, &(ref fork, block)) in spec.forks.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str(&format!("{}:{}", json_escape(fork), block));
+        }
+        json.push_str("},\"precompiles\":[");
+        for (i, address) in spec.precompiles.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str(&format!("\"0x{:x}\"", address));
+        }
+        json.push_str("]}");
+        self.print_raw(&format!("CHAIN_SPEC {}", json));
+    }
+
+    /// Prints a raw, pre-rendered line on the `DMLOG` channel, bypassing
+    /// the event encoders; used for structured JSON payloads like
+    /// `CHAIN_SPEC` and `CALL_TRACE` that do not fit the flat field model.
+    pub(crate) fn print_raw(&self, line: &str) {
+        self.printer.print(Channel::Log, line);
+    }
+
+    /// Records that block `num` with `hash` became finalized and can no
+    /// longer be reorged away. Driven by the node's finality signal; does
+    /// nothing unless `Config::finality_markers` is enabled.
+    pub fn record_finality(&self, num: u64, hash: &eth::H256) {
+        if !self.config.finality_markers {
+            return;
+        }
+        self.emit(Event::new("FINALITY").u64("num", num).h256("hash", hash));
+    }
+
+    /// Marks every event that follows as coming from a simulation —
+    /// `eth_call`, `estimateGas` or a custom dry run, named by `kind` —
+    /// rather than canonical block import, so consumers do not persist
+    /// simulated traces as canonical. Emitted by the call path when it
+    /// hands the context to a tracer, before any transaction event.
+    pub fn record_simulation_marker(&self, kind: &str) {
+        self.emit(Event::new("SIMULATION").string("kind", kind));
+    }
+
+    /// Records a canonical chain reorganisation rolling back `discarded`,
+    /// the hashes of the no-longer-canonical blocks, newest first. The
+    /// depth lets consumers size their undo, the hashes identify exactly
+    /// which blocks to discard; past [`REORG_HASH_CAP`] blocks only the
+    /// depth is emitted.
+    pub fn record_reorg(&self, discarded: &[eth::H256]) {
+        let mut event = Event::new("REORG").u64("depth", discarded.len() as u64);
+        if discarded.len() <= REORG_HASH_CAP {
+            for hash in discarded {
+                event = event.h256("discarded", hash);
+            }
+        }
+        self.emit(event);
+    }
+
+    /// Opens the instrumentation context for the import of one block.
+    pub fn block_context(self: &Arc<Context>) -> BlockContext {
+        BlockContext {
+            ctx: self.clone(),
+            state: Arc::new(BlockState::default()),
+        }
+    }
+
+    /// Renders and prints a single event, followed by its `TIMING` line when
+    /// event timing is enabled.
+    pub(crate) fn emit(&self, event: Event) {
+        let line = event.render(&self.config);
+        {
+            let mut stats = self.stats.lock();
+            match event.name() {
+                "BEGIN_BLOCK" => stats.blocks += 1,
+                "BEGIN_APPLY_TRX" => stats.transactions += 1,
+                _ => {}
+            }
+            stats.events += 1;
+            stats.bytes += line.len() as u64;
+        }
+        self.sink_for(&event).print(event.channel(), &line);
+
+        if self.config.timing {
+            if let Some(start) = *self.block_start.lock() {
+                let elapsed = (self.clock)().duration_since(start);
+                let timing = Event::debug("TIMING")
+                    .string("event", event.name())
+                    .u64("ns", elapsed.as_nanos() as u64);
+                let line = timing.render(&self.config);
+                self.sink_for(&timing).print(timing.channel(), &line);
+            }
+        }
+    }
+
+    /// Closes the stream, emitting a `STREAM_STATS` summary of everything
+    /// produced over this context's lifetime, as a quick sanity check for
+    /// operators. The summary line itself is not counted.
+    pub fn shutdown(&self) {
+        let event = {
+            let stats = self.stats.lock();
+            Event::new("STREAM_STATS")
+                .u64("blocks", stats.blocks)
+                .u64("transactions", stats.transactions)
+                .u64("events", stats.events)
+                .u64("bytes", stats.bytes)
+        };
+        let line = event.render(&self.config);
+        self.sink_for(&event).print(event.channel(), &line);
+    }
+
+    /// Captures the monotonic instant all `TIMING` lines of the current
+    /// block are relative to.
+    pub(crate) fn start_block_timer(&self) {
+        *self.block_start.lock() = Some((self.clock)());
+    }
+}
+
+/// State accumulated across the transactions of one block, shared between
+/// the block context and its transaction tracers.
+#[derive(Default)]
+pub(crate) struct BlockState {
+    /// Sum of the `gas_used` reported by every `END_APPLY_TRX` so far.
+    pub cumulative_gas_used: Mutex<u64>,
+    /// The fork whose rules price this block, for `Config::fork_tags`.
+    pub active_fork: Mutex<Option<::gas::Fork>>,
+    /// Unique addresses with any state change so far, for the
+    /// `BLOCK_ADDRESSES` summary.
+    pub touched_addresses: Mutex<HashSet<eth::Address>>,
+    /// Contracts deployed so far with their code hashes, in deployment
+    /// order, for the `BLOCK_CONTRACT_CHANGES` summary.
+    pub created_contracts: Mutex<Vec<(eth::Address, eth::H256)>>,
+    /// Contracts self-destructed so far, in destruction order, for the
+    /// `BLOCK_CONTRACT_CHANGES` summary.
+    pub destroyed_contracts: Mutex<Vec<eth::Address>>,
+    /// Component sizes of the block RLP — header, transactions, uncles,
+    /// withdrawals, in bytes — appended to `END_BLOCK` when recorded.
+    pub size_breakdown: Mutex<Option<(u64, u64, u64, u64)>>,
+}
+
+/// Instrumentation context scoped to the import of a single block.
+pub struct BlockContext {
+    ctx: Arc<Context>,
+    state: Arc<BlockState>,
+}
+
+impl BlockContext {
+    /// Declares the fork whose rules price this block, resolved by the
+    /// caller from the block number and the chain spec. Gas changes of the
+    /// block's transactions are tagged with it when `Config::fork_tags`
+    /// is enabled; without a declared fork nothing is tagged.
+    pub fn set_active_fork(&self, fork: ::gas::Fork) {
+        *self.state.active_fork.lock() = Some(fork);
+    }
+
+    /// Marks the beginning of block `num`.
+    pub fn start_block(&self, num: u64) {
+        self.ctx.start_block_timer();
+        self.ctx.emit(Event::new("BEGIN_BLOCK").u64("num", num));
+    }
+
+    /// Marks the end of block `num`, with the total block RLP `size` in
+    /// bytes and the header's `gas_used`. Cross-checks the header value
+    /// against the sum of per-transaction gas and reports any difference on
+    /// the `DMDEBUG` channel.
+    pub fn end_block(&self, num: u64, size: u64, gas_used: u64) {
+        let computed = *self.state.cumulative_gas_used.lock();
+        if computed != gas_used {
+            self.ctx.emit(
+                Event::debug("GAS_USED_MISMATCH")
+                    .u64("num", num)
+                    .gas("header", gas_used)
+                    .gas("computed", computed),
+            );
+        }
+        self.emit_block_addresses();
+        self.emit_block_contract_changes();
+        let mut event = Event::new("END_BLOCK")
+            .u64("num", num)
+            .u64("size", size)
+            .gas("gas_used", gas_used);
+        if let Some((header, transactions, uncles, withdrawals)) =
+            self.state.size_breakdown.lock().take()
+        {
+            let sum = header + transactions + uncles + withdrawals;
+            if sum != size {
+                self.ctx.emit(
+                    Event::debug("SIZE_MISMATCH")
+                        .u64("num", num)
+                        .u64("total", size)
+                        .u64("components", sum),
+                );
+            }
+            event = event
+                .u64("header_bytes", header)
+                .u64("transactions_bytes", transactions)
+                .u64("uncles_bytes", uncles)
+                .u64("withdrawals_bytes", withdrawals);
+        }
+        self.ctx.emit(event);
+    }
+
+    /// Records how the block RLP's size splits across its components —
+    /// header, transaction list, uncle list and withdrawal list, in bytes —
+    /// for data-availability and bandwidth cost analysis. The breakdown is
+    /// appended to `END_BLOCK` after the total size; components that do not
+    /// sum to it are reported as a `SIZE_MISMATCH` warning on the `DMDEBUG`
+    /// channel, mirroring the gas-used cross-check. Pre-Shanghai blocks
+    /// pass zero withdrawal bytes.
+    pub fn record_size_breakdown(
+        &self,
+        header: u64,
+        transactions: u64,
+        uncles: u64,
+        withdrawals: u64,
+    ) {
+        *self.state.size_breakdown.lock() = Some((header, transactions, uncles, withdrawals));
+    }
+
+    /// Cross-checks the header's `logs_bloom` of block `num` against the
+    /// OR of all its transaction logs blooms, reporting a `BLOOM_MISMATCH`
+    /// on the `DMDEBUG` channel when they differ — like the `gas_used`
+    /// check of `end_block`, a mismatch means the stream's logs do not
+    /// reproduce the header. Does nothing unless `Config::verify_bloom`
+    /// is enabled.
+    pub fn verify_logs_bloom(
+        &self,
+        num: u64,
+        header_bloom: &eth::Bloom,
+        transaction_blooms: &[eth::Bloom],
+    ) {
+        if !self.ctx.config().verify_bloom {
+            return;
+        }
+        let mut computed = eth::Bloom::zero();
+        for bloom in transaction_blooms {
+            computed |= *bloom;
+        }
+        if computed != *header_bloom {
+            self.ctx.emit(
+                Event::debug("BLOOM_MISMATCH")
+                    .u64("num", num)
+                    .bytes("header", header_bloom.as_bytes())
+                    .bytes("computed", computed.as_bytes()),
+            );
+        }
+    }
+
+    /// Summarises the unique addresses touched by any state change over
+    /// the block as one `BLOCK_ADDRESSES` line before `END_BLOCK`, a
+    /// lightweight activity metric. `Config::block_address_detail`
+    /// controls whether the sorted addresses follow the count; blocks
+    /// that touched nothing stay silent.
+    fn emit_block_addresses(&self) {
+        let detail = self.ctx.config().block_address_detail;
+        if detail == BlockAddressDetail::None {
+            return;
+        }
+        let touched = self.state.touched_addresses.lock();
+        if touched.is_empty() {
+            return;
+        }
+        let mut event = Event::new("BLOCK_ADDRESSES").u64("count", touched.len() as u64);
+        if detail == BlockAddressDetail::Full {
+            let sorted: BTreeSet<&eth::Address> = touched.iter().collect();
+            for address in sorted {
+                event = event.address("address", address);
+            }
+        }
+        self.ctx.emit(event);
+    }
+
+    /// Summarises the block's contract lifecycle as one
+    /// `BLOCK_CONTRACT_CHANGES` line before `END_BLOCK`: the count of
+    /// deployed contracts followed by their `(address, code hash)` pairs in
+    /// deployment order, then the count of self-destructed contracts
+    /// followed by their addresses, giving lifecycle indexers a per-block
+    /// view without processing every call. Blocks that neither deployed
+    /// nor destroyed anything stay silent.
+    fn emit_block_contract_changes(&self) {
+        let created = self.state.created_contracts.lock();
+        let destroyed = self.state.destroyed_contracts.lock();
+        if created.is_empty() && destroyed.is_empty() {
+            return;
+        }
+        let mut event = Event::new("BLOCK_CONTRACT_CHANGES").u64("created", created.len() as u64);
+        for &(ref address, ref code_hash) in created.iter() {
+            event = event.address("address", address).h256("code_hash", code_hash);
+        }
+        event = event.u64("destroyed", destroyed.len() as u64);
+        for address in destroyed.iter() {
+            event = event.address("address", address);
+        }
+        self.ctx.emit(event);
+    }
+
+    /// Records the block's aggregated supply movement at `end_block`:
+    /// `minted` is the issuance credited to the author and uncles, `burned`
+    /// the base fee (and blob fee) destroyed. The net delta is derived here
+    /// rather than passed in so the three figures can never disagree; since
+    /// post-London blocks can burn more than they mint, it renders as a
+    /// sign-prefixed hex quantity.
+    pub fn record_supply_delta(&self, minted: &eth::U256, burned: &eth::U256) {
+        let net = if minted >= burned {
+            format!("{:x}", *minted - *burned)
+        } else {
+            format!("-{:x}", *burned - *minted)
+        };
+        self.ctx.emit(
+            Event::new("SUPPLY_DELTA")
+                .u256("minted", minted)
+                .u256("burned", burned)
+                .string("net", &net),
+        );
+    }
+
+    /// Records one beacon chain withdrawal (EIP-4895) credited by this
+    /// block, as a `WITHDRAWAL` marker followed by the `BALANCE_CHANGE`
+    /// it causes, so consumers get the structured withdrawal and its
+    /// balance effect as an adjacent pair. Withdrawals happen outside any
+    /// transaction, so the balance change carries call index 0;
+    /// `balance_before` is the recipient's balance before the credit and
+    /// `amount` is already converted from gwei to wei. Shanghai blocks on.
+    pub fn record_withdrawal(
+        &self,
+        index: u64,
+        validator_index: u64,
+        address: &eth::Address,
+        amount: &eth::U256,
+        balance_before: &eth::U256,
+    ) {
+        self.state.touched_addresses.lock().insert(*address);
+        self.ctx.emit(
+            Event::new("WITHDRAWAL")
+                .u64("index", index)
+                .u64("validator_index", validator_index)
+                .address("address", address)
+                .u256("amount", amount),
+        );
+        let balance_after = *balance_before + *amount;
+        self.ctx.emit(
+            Event::new("BALANCE_CHANGE")
+                .u64("call_index", 0)
+                .address("address", address)
+                .u256("old", balance_before)
+                .u256("new", &balance_after)
+                .string("reason", ::gas::BalanceChangeReason::Withdrawal.as_str()),
+        );
+    }
+
+    /// Records the uncles included in block `num`, given their RLP encoded
+    /// headers. `Config::uncle_detail` controls whether the full headers,
+    /// only the count, or nothing is emitted. Pre-merge blocks only.
+    pub fn record_uncles(&self, num: u64, uncles: &[Vec<u8>]) {
+        let detail = self.ctx.config().uncle_detail;
+        if detail == UncleDetail::None {
+            return;
+        }
+        let mut event = Event::new("UNCLES")
+            .u64("num", num)
+            .u64("count", uncles.len() as u64);
+        if detail == UncleDetail::Full {
+            for uncle in uncles {
+                event = event.bytes("header", uncle);
+            }
+        }
+        self.ctx.emit(event);
+    }
+
+    /// Records the block's transaction hashes in inclusion order as one
+    /// `TRANSACTIONS_LIST` line, the count followed by the hashes. The
+    /// trie keyed by RLP-encoded index over these hashes must reproduce
+    /// the header's `transactions_root`, giving consumers a Merkle check
+    /// of the list. Does nothing unless `Config::transactions_list` is
+    /// enabled.
+    pub fn record_transactions_list(&self, hashes: &[eth::H256]) {
+        if !self.ctx.config().transactions_list {
+            return;
+        }
+        let mut event = Event::new("TRANSACTIONS_LIST").u64("count", hashes.len() as u64);
+        for hash in hashes {
+            event = event.h256("hash", hash);
+        }
+        self.ctx.emit(event);
+    }
+
+    /// Records the EIP-1559 base fee computation of this block: the
+    /// parent's base fee, the parent's gas usage against its gas target
+    /// (`gas_limit / elasticity_multiplier`), and the resulting base fee.
+    /// The adjustment direction is derived from usage versus target —
+    /// `up` above, `down` below, `stable` on it — so consumers can verify
+    /// the base fee formula without re-deriving the target themselves.
+    /// Post-London blocks only.
+    pub fn record_base_fee_computation(
+        &self,
+        parent_base_fee: &eth::U256,
+        gas_used: u64,
+        gas_target: u64,
+        new_base_fee: &eth::U256,
+    ) {
+        let direction = if gas_used > gas_target {
+            "up"
+        } else if gas_used < gas_target {
+            "down"
+        } else {
+            "stable"
+        };
+        self.ctx.emit(
+            Event::new("BASE_FEE_COMPUTATION")
+                .u256("parent_base_fee", parent_base_fee)
+                .gas("gas_used", gas_used)
+                .gas("gas_target", gas_target)
+                .string("direction", direction)
+                .u256("new_base_fee", new_base_fee),
+        );
+    }
+
+    /// Records the ordered hashes of the block's uncle headers as one
+    /// `UNCLE_HASHES` line, the count followed by the hashes. The keccak
+    /// of the RLP list of the headers behind these hashes must reproduce
+    /// the header's `sha3_uncles`, complementing the full headers of
+    /// `record_uncles` with a cheap verification handle. Pre-merge blocks
+    /// only.
+    pub fn record_uncle_hashes(&self, hashes: &[eth::H256]) {
+        let mut event = Event::new("UNCLE_HASHES").u64("count", hashes.len() as u64);
+        for hash in hashes {
+            event = event.h256("hash", hash);
+        }
+        self.ctx.emit(event);
+    }
+
+    /// Records the header `extra_data` of block `num`. Always carries the
+    /// raw bytes; when they form valid UTF-8 without whitespace or control
+    /// characters — the usual producer banner, e.g. a client name or pool
+    /// tag — a decoded field follows so operators can read it off the
+    /// stream directly. Anything else omits the decoded field.
+    pub fn record_extra_data(&self, num: u64, extra_data: &[u8]) {
+        let mut event = Event::new("EXTRA_DATA")
+            .u64("num", num)
+            .bytes("raw", extra_data);
+        if let Ok(decoded) = ::std::str::from_utf8(extra_data) {
+            if !decoded.is_empty()
+                && decoded.chars().all(|c| !c.is_whitespace() && !c.is_control())
+            {
+                event = event.string("decoded", decoded);
+            }
+        }
+        self.ctx.emit(event);
+    }
+
+    /// Records the consensus type of block `num`, classified from the
+    /// header `difficulty`: post-merge blocks carry a difficulty of zero and
+    /// are produced by a validator, making the header's `miner` field a fee
+    /// recipient rather than a PoW miner. The marker carries the coinbase
+    /// with its correct role so consumers do not misinterpret it.
+    pub fn record_consensus_type(
+        &self,
+        num: u64,
+        difficulty: &eth::U256,
+        coinbase: &eth::Address,
+    ) {
+        let pos = difficulty.is_zero();
+        self.ctx.emit(
+            Event::new("CONSENSUS_TYPE")
+                .u64("num", num)
+                .string("consensus", if pos { "pos" } else { "pow" })
+                .string("coinbase_role", if pos { "fee_recipient" } else { "miner" })
+                .address("coinbase", coinbase),
+        );
+    }
+
+    /// Records the receipts root of block `num` as a standalone checkpoint,
+    /// so consumers reconstructing receipts incrementally can verify them
+    /// without parsing the full header.
+    pub fn record_receipts_root(&self, num: u64, root: &eth::H256) {
+        self.ctx
+            .emit(Event::new("RECEIPTS_ROOT").u64("num", num).h256("root", root));
+    }
+
+    /// Records the reward credited for including an uncle, together with the
+    /// inputs of the reward formula
+    /// `((uncle_number + 8 - block_number) * base_reward) / 8`, so consumers
+    /// can verify the reward independently. Pre-merge blocks only.
+    pub fn record_uncle_reward(
+        &self,
+        miner: &eth::Address,
+        reward: &eth::U256,
+        uncle_number: u64,
+        block_number: u64,
+        base_reward: &eth::U256,
+    ) {
+        self.ctx.emit(
+            Event::new("UNCLE_REWARD")
+                .address("miner", miner)
+                .u256("reward", reward)
+                .u64("uncle_number", uncle_number)
+                .u64("block_number", block_number)
+                .u256("base_reward", base_reward),
+        );
+    }
+
+    /// Opens the tracer for one transaction of this block.
+    pub fn transaction_tracer(&self) -> TransactionTracer {
+        TransactionTracer::new(self.ctx.clone(), self.state.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eth::{Address, U256};
+    use printer::MemoryPrinter;
+
+    fn test_context() -> (Arc<Context>, Arc<MemoryPrinter>) {
+        let printer = Arc::new(MemoryPrinter::new());
+        (Context::new(Config::default(), printer.clone()), printer)
+    }
+
+    #[test]
+    fn uncle_reward_carries_formula_inputs() {
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+        let base_reward = U256::from(5_000_000_000_000_000_000u64);
+        let block_number = 1000u64;
+
+        for &distance in &[1u64, 2, 7] {
+            let uncle_number = block_number - distance;
+            let reward =
+                base_reward * U256::from(uncle_number + 8 - block_number) / U256::from(8);
+            block.record_uncle_reward(
+                &Address::from_low_u64_be(1),
+                &reward,
+                uncle_number,
+                block_number,
+                &base_reward,
+            );
+        }
+
+        let lines = printer.lines();
+        assert_eq!(lines.len(), 3);
+        for line in &lines {
+            let fields: Vec<&str> = line.split(' ').collect();
+            assert_eq!(fields[0], "DMLOG");
+            assert_eq!(fields[1], "UNCLE_REWARD");
+            let reward: U256 = fields[3].parse().unwrap();
+            let uncle_number: u64 = fields[4].parse().unwrap();
+            let block_number: u64 = fields[5].parse().unwrap();
+            let base_reward: U256 = fields[6].parse().unwrap();
+            assert_eq!(
+                reward,
+                base_reward * U256::from(uncle_number + 8 - block_number) / U256::from(8)
+            );
+        }
+    }
+
+    #[test]
+    fn receipts_root_emits_standalone_checkpoint() {
+        use eth::H256;
+
+        let (ctx, printer) = test_context();
+        let root = H256::from_low_u64_be(0xdead);
+        ctx.block_context().record_receipts_root(42, &root);
+
+        assert_eq!(
+            printer.lines(),
+            vec![format!("DMLOG RECEIPTS_ROOT 42 {:x}", root)]
+        );
+    }
+
+    #[test]
+    fn finality_marker_is_gated_by_config() {
+        use eth::H256;
+
+        let hash = H256::from_low_u64_be(0xf1a1);
+        for &(enabled, ref expected) in &[
+            (true, vec![format!("DMLOG FINALITY 100 {:x}", hash)]),
+            (false, Vec::new()),
+        ] {
+            let printer = Arc::new(MemoryPrinter::new());
+            let config = Config {
+                finality_markers: enabled,
+                ..Default::default()
+            };
+            let ctx = Context::new(config, printer.clone());
+            ctx.record_finality(100, &hash);
+            assert_eq!(printer.lines(), *expected);
+        }
+    }
+
+    #[test]
+    fn reorg_carries_depth_and_discarded_hashes() {
+        use eth::H256;
+
+        let (ctx, printer) = test_context();
+        let discarded: Vec<H256> = (1..=3u64).map(H256::from_low_u64_be).collect();
+        ctx.record_reorg(&discarded);
+
+        assert_eq!(
+            printer.lines(),
+            vec![format!(
+                "DMLOG REORG 3 {:x} {:x} {:x}",
+                discarded[0], discarded[1], discarded[2]
+            )]
+        );
+    }
+
+    #[test]
+    fn deep_reorg_falls_back_to_depth_only() {
+        use eth::H256;
+
+        let (ctx, printer) = test_context();
+        let discarded: Vec<H256> = (1..=100u64).map(H256::from_low_u64_be).collect();
+        ctx.record_reorg(&discarded);
+
+        assert_eq!(printer.lines(), vec!["DMLOG REORG 100".to_owned()]);
+    }
+
+    #[test]
+    fn chain_spec_manifest_is_a_single_json_line() {
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            chain_spec_manifest: true,
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let spec = ChainSpec {
+            name: "foundation".to_owned(),
+            chain_id: 1,
+            forks: vec![
+                ("homestead".to_owned(), 1_150_000),
+                ("byzantium".to_owned(), 4_370_000),
+                ("london".to_owned(), 12_965_000),
+            ],
+            precompiles: (1..=9u64).map(Address::from_low_u64_be).collect(),
+        };
+        ctx.record_chain_spec(&spec);
+
+        let lines = printer.lines();
+        assert_eq!(lines.len(), 1);
+        let line = &lines[0];
+        assert!(line.starts_with("DMLOG CHAIN_SPEC {"));
+        assert!(line.contains("\"name\":\"foundation\""));
+        assert!(line.contains("\"chain_id\":1"));
+        assert!(line.contains("\"london\":12965000"));
+        assert!(line.contains(&format!("\"0x{:x}\"", Address::from_low_u64_be(9))));
+
+        // Gated off by default.
+        let (plain_ctx, plain_printer) = test_context();
+        plain_ctx.record_chain_spec(&spec);
+        assert!(plain_printer.lines().is_empty());
+    }
+
+    #[test]
+    fn supply_delta_nets_minting_against_burn() {
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+
+        // A post-London block: 2 ETH issuance against the base fee burned
+        // by three transactions, which here outweighs the reward.
+        let minted = U256::from(2_000_000_000_000_000_000u64);
+        let burned: U256 = [11_000_000u64, 10_000_000, 9_000_000]
+            .iter()
+            .map(|gas| U256::from(*gas) * U256::from(100_000_000_000u64))
+            .fold(U256::zero(), |acc, fee| acc + fee);
+        block.record_supply_delta(&minted, &burned);
+        assert!(burned > minted);
+
+        assert_eq!(
+            printer.lines(),
+            vec![format!(
+                "DMLOG SUPPLY_DELTA {:x} {:x} -{:x}",
+                minted,
+                burned,
+                burned - minted
+            )]
+        );
+    }
+
+    #[test]
+    fn extra_data_decodes_valid_utf8_banners() {
+        use rustc_hex::ToHex;
+
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+
+        // A mainnet-style producer banner, decodable as-is.
+        block.record_extra_data(1, b"ethermine-eu1");
+        // Arbitrary bytes: raw hex only.
+        block.record_extra_data(2, &[0xff, 0xfe, 0x00]);
+
+        assert_eq!(
+            printer.lines(),
+            vec![
+                format!(
+                    "DMLOG EXTRA_DATA 1 {} ethermine-eu1",
+                    b"ethermine-eu1".to_hex()
+                ),
+                "DMLOG EXTRA_DATA 2 fffe00".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn consensus_type_classifies_post_merge_blocks_as_pos() {
+        let (ctx, printer) = test_context();
+        let coinbase = Address::from_low_u64_be(0xfee);
+        let block = ctx.block_context();
+
+        // Post-merge header: difficulty is always zero.
+        block.record_consensus_type(17_000_000, &U256::zero(), &coinbase);
+        // Pre-merge header: a real PoW difficulty.
+        block.record_consensus_type(1_000_000, &U256::from(0x1b4fd0u64), &coinbase);
+
+        assert_eq!(
+            printer.lines(),
+            vec![
+                format!("DMLOG CONSENSUS_TYPE 17000000 pos fee_recipient {:x}", coinbase),
+                format!("DMLOG CONSENSUS_TYPE 1000000 pow miner {:x}", coinbase),
+            ]
+        );
+    }
+
+    #[test]
+    fn base_fee_computation_moves_up_for_an_above_target_block() {
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+
+        // A full 30M-gas parent against a 15M target: usage is double the
+        // target, so the base fee rises by the maximum 12.5%.
+        let parent_base_fee = U256::from(40_000_000_000u64);
+        let new_base_fee = parent_base_fee + parent_base_fee / U256::from(8);
+        block.record_base_fee_computation(&parent_base_fee, 30_000_000, 15_000_000, &new_base_fee);
+        assert!(new_base_fee > parent_base_fee);
+
+        assert_eq!(
+            printer.lines(),
+            vec![format!(
+                "DMLOG BASE_FEE_COMPUTATION {:x} 30000000 15000000 up {:x}",
+                parent_base_fee, new_base_fee
+            )]
+        );
+    }
+
+    #[test]
+    fn uncle_hashes_are_emitted_in_order() {
+        use eth::H256;
+        use keccak_hash::keccak;
+
+        let (ctx, printer) = test_context();
+        // Two uncle headers, identified by the keccak of their RLP.
+        let headers = [vec![0xc1u8, 0x80], vec![0xc2u8, 0x80, 0x80]];
+        let hashes: Vec<H256> = headers.iter().map(keccak).collect();
+        ctx.block_context().record_uncle_hashes(&hashes);
+
+        assert_eq!(
+            printer.lines(),
+            vec![format!("DMLOG UNCLE_HASHES 2 {:x} {:x}", hashes[0], hashes[1])]
+        );
+    }
+
+    #[test]
+    fn block_addresses_count_distinct_touched_addresses() {
+        use config::BlockAddressDetail;
+        use eth::H256;
+        use gas::BalanceChangeReason;
+        use tracer::Tracer;
+
+        let a = Address::from_low_u64_be(0xaa);
+        let b = Address::from_low_u64_be(0xbb);
+
+        for &(detail, ref expected) in &[
+            (BlockAddressDetail::CountOnly, vec!["DMLOG BLOCK_ADDRESSES 2".to_owned()]),
+            (
+                BlockAddressDetail::Full,
+                vec![format!("DMLOG BLOCK_ADDRESSES 2 {:x} {:x}", a, b)],
+            ),
+            (BlockAddressDetail::None, Vec::new()),
+        ] {
+            let printer = Arc::new(MemoryPrinter::new());
+            let config = Config {
+                block_address_detail: detail,
+                ..Default::default()
+            };
+            let ctx = Context::new(config, printer.clone());
+            let block = ctx.block_context();
+            block.start_block(1);
+
+            // Two transactions touching A twice and B once: two distinct
+            // addresses over the block.
+            let mut first = block.transaction_tracer();
+            first.record_balance_change(
+                &a,
+                &U256::zero(),
+                &U256::from(1),
+                BalanceChangeReason::Transfer,
+            );
+            first.record_storage_change(
+                &a,
+                &H256::from_low_u64_be(1),
+                &H256::zero(),
+                &H256::from_low_u64_be(2),
+            );
+            first.end_apply_trx(30_000, None);
+            let mut second = block.transaction_tracer();
+            second.record_balance_change(
+                &b,
+                &U256::zero(),
+                &U256::from(1),
+                BalanceChangeReason::Transfer,
+            );
+            second.end_apply_trx(21_000, None);
+            block.end_block(1, 1024, 51_000);
+
+            let summaries: Vec<String> = printer
+                .lines()
+                .into_iter()
+                .filter(|line| line.starts_with("DMLOG BLOCK_ADDRESSES"))
+                .collect();
+            assert_eq!(summaries, *expected);
+        }
+
+        // An empty block stays silent.
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+        block.start_block(2);
+        block.end_block(2, 512, 0);
+        assert!(printer
+            .lines()
+            .iter()
+            .all(|line| !line.contains("BLOCK_ADDRESSES")));
+    }
+
+    #[test]
+    fn transactions_list_is_gated_and_ordered() {
+        use eth::H256;
+
+        let hashes: Vec<H256> = (1..=3u64).map(H256::from_low_u64_be).collect();
+
+        for &(enabled, expected) in &[(true, 1usize), (false, 0)] {
+            let printer = Arc::new(MemoryPrinter::new());
+            let config = Config {
+                transactions_list: enabled,
+                ..Default::default()
+            };
+            let ctx = Context::new(config, printer.clone());
+            ctx.block_context().record_transactions_list(&hashes);
+
+            let lines = printer.lines();
+            assert_eq!(lines.len(), expected);
+            if enabled {
+                assert_eq!(
+                    lines[0],
+                    format!(
+                        "DMLOG TRANSACTIONS_LIST 3 {:x} {:x} {:x}",
+                        hashes[0], hashes[1], hashes[2]
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn withdrawals_pair_markers_with_balance_changes() {
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+        let gwei = U256::from(1_000_000_000u64);
+
+        // Two withdrawals to the same recipient, as a validator sweeping
+        // rewards twice in one block's list would produce.
+        let recipient = Address::from_low_u64_be(0xfee);
+        let balance = U256::from(5) * gwei;
+        let first = U256::from(3_000_000u64) * gwei;
+        let second = U256::from(18_000u64) * gwei;
+        block.record_withdrawal(41_293_001, 517_002, &recipient, &first, &balance);
+        block.record_withdrawal(41_293_002, 517_002, &recipient, &second, &(balance + first));
+
+        assert_eq!(
+            printer.lines(),
+            vec![
+                format!("DMLOG WITHDRAWAL 41293001 517002 {:x} {:x}", recipient, first),
+                format!(
+                    "DMLOG BALANCE_CHANGE 0 {:x} {:x} {:x} withdrawal",
+                    recipient,
+                    balance,
+                    balance + first
+                ),
+                format!("DMLOG WITHDRAWAL 41293002 517002 {:x} {:x}", recipient, second),
+                format!(
+                    "DMLOG BALANCE_CHANGE 0 {:x} {:x} {:x} withdrawal",
+                    recipient,
+                    balance + first,
+                    balance + first + second
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn bloom_mismatch_is_reported_when_verification_is_enabled() {
+        use eth::Bloom;
+        use rustc_hex::ToHex;
+
+        let a = Bloom::from_low_u64_be(0b0011);
+        let b = Bloom::from_low_u64_be(0b0101);
+        let aggregate = a | b;
+        // A header claiming one bit the transactions never set.
+        let wrong = Bloom::from_low_u64_be(0b1111);
+
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            verify_bloom: true,
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let block = ctx.block_context();
+
+        block.verify_logs_bloom(7, &aggregate, &[a, b]);
+        assert!(printer.lines_on(::printer::Channel::Debug).is_empty());
+
+        block.verify_logs_bloom(7, &wrong, &[a, b]);
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Debug),
+            vec![format!(
+                "BLOOM_MISMATCH 7 {} {}",
+                wrong.as_bytes().to_hex(),
+                aggregate.as_bytes().to_hex()
+            )]
+        );
+
+        // Gated off by default.
+        let (plain_ctx, plain_printer) = test_context();
+        plain_ctx.block_context().verify_logs_bloom(7, &wrong, &[a, b]);
+        assert!(plain_printer.lines_on(::printer::Channel::Debug).is_empty());
+    }
+
+    #[test]
+    fn shutdown_emits_stream_stats() {
+        use eth::{H256, U256};
+
+        let (ctx, printer) = test_context();
+        let mut bytes = 0u64;
+        for num in 1..3u64 {
+            let block = ctx.block_context();
+            block.start_block(num);
+            let mut tracer = block.transaction_tracer();
+            tracer.begin_apply_trx(
+                &H256::from_low_u64_be(num),
+                None,
+                &U256::zero(),
+                21000,
+                &U256::from(1u64),
+                0,
+                &[],
+                Some(1),
+            );
+            tracer.end_apply_trx(21000, None);
+            block.end_block(num, 500, 21000);
+        }
+        for line in printer.lines() {
+            bytes += line.len() as u64 - "DMLOG ".len() as u64;
+        }
+        ctx.shutdown();
+
+        assert_eq!(
+            printer.lines().last().unwrap(),
+            &format!("DMLOG STREAM_STATS 2 2 8 {}", bytes)
+        );
+    }
+
+    #[test]
+    fn uncle_detail_controls_uncles_event() {
+        let uncles = vec![vec![0xaau8, 0xbb], vec![0xccu8, 0xdd]];
+
+        for &(detail, ref expected) in &[
+            (UncleDetail::Full, vec!["DMLOG UNCLES 9 2 aabb ccdd".to_owned()]),
+            (UncleDetail::CountOnly, vec!["DMLOG UNCLES 9 2".to_owned()]),
+            (UncleDetail::None, Vec::new()),
+        ] {
+            let printer = Arc::new(MemoryPrinter::new());
+            let config = Config {
+                uncle_detail: detail,
+                ..Default::default()
+            };
+            let ctx = Context::new(config, printer.clone());
+            ctx.block_context().record_uncles(9, &uncles);
+            assert_eq!(printer.lines(), *expected);
+        }
+    }
+
+    #[test]
+    fn block_contract_changes_summarise_the_lifecycle() {
+        use keccak_hash::keccak;
+        use tracer::Tracer;
+
+        let a = Address::from_low_u64_be(0xaa);
+        let b = Address::from_low_u64_be(0xbb);
+        let code = [0x60u8, 0x00, 0x60, 0x00, 0xf3];
+
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+        block.start_block(7);
+        {
+            let mut tracer = block.transaction_tracer();
+            tracer.record_contract_deployed(&a, &code, 10_000);
+            tracer.record_selfdestruct(&b, &a, &::eth::U256::zero());
+        }
+        block.end_block(7, 1024, 0);
+
+        let expected = format!(
+            "DMLOG BLOCK_CONTRACT_CHANGES 1 {:x} {:x} 1 {:x}",
+            a,
+            keccak(&code[..]),
+            b
+        );
+        let lines = printer.lines();
+        assert!(lines.contains(&expected));
+
+        // A block without deployments or destructions stays silent.
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+        block.start_block(8);
+        block.end_block(8, 512, 0);
+        assert!(printer
+            .lines()
+            .iter()
+            .all(|line| !line.contains("BLOCK_CONTRACT_CHANGES")));
+    }
+
+    #[test]
+    fn simulation_marker_precedes_the_simulated_trace() {
+        let (ctx, printer) = test_context();
+        // An eth_call dry run: the marker opens the stream, then the
+        // simulated transaction traces as usual.
+        ctx.record_simulation_marker("eth_call");
+        let mut tracer = ctx.block_context().transaction_tracer();
+        tracer.end_apply_trx(21_000, None);
+
+        assert_eq!(
+            printer.lines(),
+            vec![
+                "DMLOG SIMULATION eth_call".to_owned(),
+                "DMLOG END_APPLY_TRX 21000".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn end_block_checks_cumulative_gas_used() {
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+        block.start_block(7);
+        for gas_used in &[21000u64, 53000] {
+            let mut tracer = block.transaction_tracer();
+            tracer.end_apply_trx(*gas_used, None);
+        }
+        block.end_block(7, 1024, 74000);
+
+        assert!(printer.lines_on(::printer::Channel::Debug).is_empty());
+        assert_eq!(
+            printer.lines().last().unwrap(),
+            "DMLOG END_BLOCK 7 1024 74000"
+        );
+    }
+
+    #[test]
+    fn size_breakdown_components_sum_to_the_block_size() {
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+        block.start_block(7);
+        // 512-byte header, 400 bytes of transactions, 100 of uncles, 12 of
+        // withdrawals: 1024 in total, matching the size END_BLOCK carries.
+        block.record_size_breakdown(512, 400, 100, 12);
+        block.end_block(7, 1024, 0);
+
+        assert!(printer.lines_on(::printer::Channel::Debug).is_empty());
+        assert_eq!(
+            printer.lines().last().unwrap(),
+            "DMLOG END_BLOCK 7 1024 0 512 400 100 12"
+        );
+
+        // Components not covering the total betray a measurement bug.
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+        block.start_block(8);
+        block.record_size_breakdown(512, 400, 100, 0);
+        block.end_block(8, 1024, 0);
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Debug),
+            vec!["SIZE_MISMATCH 8 1024 1012".to_owned()]
+        );
+    }
+
+    #[test]
+    fn end_block_reports_gas_used_mismatch() {
+        let (ctx, printer) = test_context();
+        let block = ctx.block_context();
+        block.start_block(7);
+        block.transaction_tracer().end_apply_trx(21000, None);
+        block.end_block(7, 1024, 74000);
+
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Debug),
+            vec!["GAS_USED_MISMATCH 7 74000 21000".to_owned()]
+        );
+    }
+
+    #[test]
+    fn routes_send_each_category_to_its_sink() {
+        use eth::H256;
+        use tracer::Tracer;
+
+        let (ctx, default_sink) = test_context();
+        let balance_sink = Arc::new(MemoryPrinter::new());
+        let storage_sink = Arc::new(MemoryPrinter::new());
+        let gas_sink = Arc::new(MemoryPrinter::new());
+        ctx.route(EventCategory::Balance, balance_sink.clone());
+        ctx.route(EventCategory::Storage, storage_sink.clone());
+        ctx.route(EventCategory::Gas, gas_sink.clone());
+
+        let mut tracer = ctx.block_context().transaction_tracer();
+        let address = Address::from_low_u64_be(0xc0de);
+        tracer.record_balance_change(
+            &address,
+            &U256::zero(),
+            &U256::from(1),
+            ::gas::BalanceChangeReason::Transfer,
+        );
+        tracer.record_storage_change(
+            &address,
+            &H256::from_low_u64_be(1),
+            &H256::zero(),
+            &H256::from_low_u64_be(2),
+        );
+        tracer.record_sload_gas(&address, &H256::from_low_u64_be(1), ::gas::Fork::Berlin, true);
+        tracer.record_hashing_precompile_gas(&Address::from_low_u64_be(2), 32);
+        tracer.end_apply_trx(21000, None);
+
+        assert_eq!(balance_sink.lines().len(), 1);
+        assert!(balance_sink.lines()[0].starts_with("DMLOG BALANCE_CHANGE "));
+        // Read and write gas metering and the end-of-transaction storage
+        // summary all follow the storage category.
+        assert_eq!(storage_sink.lines().len(), 3);
+        assert!(storage_sink.lines()[0].starts_with("DMLOG STORAGE_CHANGE "));
+        assert!(storage_sink.lines()[1].starts_with("DMLOG SLOAD_GAS "));
+        assert!(storage_sink.lines()[2].starts_with("DMLOG STORAGE_STATS "));
+        // Precompile gas and its summary follow the gas category.
+        assert_eq!(gas_sink.lines().len(), 2);
+        assert!(gas_sink.lines()[0].starts_with("DMLOG HASHING_PRECOMPILE_GAS "));
+        assert!(gas_sink.lines()[1].starts_with("DMLOG PRECOMPILE_GAS_TOTAL "));
+        // Unrouted categories still reach the default printer.
+        assert_eq!(default_sink.lines(), vec!["DMLOG END_APPLY_TRX 21000".to_owned()]);
+    }
+
+    #[test]
+    fn timing_lines_use_the_block_start_clock() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::time::{Duration, Instant};
+
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            timing: true,
+            ..Default::default()
+        };
+        // Fixed monotonic clock advancing 100ns per reading.
+        let base = Instant::now();
+        let readings = AtomicU64::new(0);
+        let clock = Box::new(move || {
+            base + Duration::from_nanos(readings.fetch_add(1, Ordering::SeqCst) * 100)
+        });
+        let ctx = Context::with_clock(config, printer.clone(), clock);
+
+        let block = ctx.block_context();
+        block.start_block(1);
+        block.end_block(1, 0, 0);
+
+        let debug = printer.lines_on(::printer::Channel::Debug);
+        assert_eq!(
+            debug,
+            vec![
+                // The timer reads the clock first (0ns), each event then
+                // takes one reading of its own.
+                "TIMING BEGIN_BLOCK 100".to_owned(),
+                "TIMING END_BLOCK 200".to_owned(),
+            ]
+        );
+    }
+}
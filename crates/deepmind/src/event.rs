@@ -0,0 +1,523 @@
+// Copyright 2015-2020 Parity Technologies (UK) Ltd.
+// This file is part of OpenEthereum.
+
+// OpenEthereum is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// OpenEthereum is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with OpenEthereum.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Typed instrumentation events and their encodings.
+//!
+//! Every emission goes through [`Event`], which carries the event name, the
+//! output channel and an ordered list of named, typed fields. The encoders
+//! derive both the positional text format and the JSON format from the same
+//! event, so the two can never drift apart.
+
+use config::{Config, EmptySentinel, Format};
+use eth::{Address, H256, U256};
+use printer::Channel;
+use rustc_hex::ToHex;
+
+/// Version of the per-event JSON schema, carried in the envelope when
+/// `Config::schema_envelope` is enabled.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Coarse grouping of events, used by `Context::route` to direct whole
+/// categories to separate sinks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventCategory {
+    /// Block lifecycle events.
+    Block,
+    /// Transaction lifecycle events.
+    Transaction,
+    /// Call frame lifecycle and EVM execution events.
+    Call,
+    /// Balance changes and reads.
+    Balance,
+    /// Storage changes and their gas metering.
+    Storage,
+    /// Gas changes and refunds.
+    Gas,
+    /// Log emissions.
+    Log,
+    /// Everything else, including the stream handshake and diagnostics.
+    Other,
+}
+
+/// A single typed event field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    /// Unsigned integer, rendered in decimal.
+    U64(u64),
+    /// Gas quantity, rendered in decimal; quoted in JSON mode when
+    /// `Config::gas_as_string` is enabled.
+    Gas(u64),
+    /// Signed integer, rendered in decimal.
+    I64(i64),
+    /// 256-bit unsigned integer, rendered as minimal hex; zero renders as `.`.
+    U256(U256),
+    /// Account address, rendered as 40-char hex; zero renders as `.`.
+    Address(Address),
+    /// 256-bit hash, rendered as 64-char hex; zero renders as `.`.
+    H256(H256),
+    /// Raw bytes, rendered as hex; empty renders as `.`.
+    Bytes(Vec<u8>),
+    /// Literal string, rendered as-is in text mode. Must not contain the
+    /// field delimiter.
+    Str(String),
+    /// Boolean, rendered as `true`/`false`.
+    Bool(bool),
+    /// Absent value, rendered as `.` in text mode and `null` in JSON mode.
+    Null,
+}
+
+impl FieldValue {
+    fn to_text(&self, config: &Config) -> String {
+        let zero = match config.empty_sentinel {
+            EmptySentinel::Dot => ".",
+            EmptySentinel::Empty => "",
+            EmptySentinel::ZeroHex => "0x0",
+        };
+        match *self {
+            FieldValue::U64(v) | FieldValue::Gas(v) => format!("{}", v),
+            FieldValue::I64(v) => format!("{}", v),
+            FieldValue::U256(ref v) => {
+                if config.fixed_width_hex {
+                    // The `uint` LowerHex impl ignores width/fill flags, so
+                    // the padding has to be explicit.
+                    format!("{:0>64}", format!("{:x}", v))
+                } else if v.is_zero() {
+                    zero.into()
+                } else {
+                    format!("{:x}", v)
+                }
+            }
+            FieldValue::Address(ref v) => {
+                if v.is_zero() && !config.fixed_width_hex {
+                    zero.into()
+                } else {
+                    format!("{:x}", v)
+                }
+            }
+            FieldValue::H256(ref v) => {
+                if v.is_zero() && !config.fixed_width_hex {
+                    zero.into()
+                } else {
+                    format!("{:x}", v)
+                }
+            }
+            FieldValue::Bytes(ref v) => {
+                if v.is_empty() {
+                    match config.empty_sentinel {
+                        EmptySentinel::Dot => ".".into(),
+                        EmptySentinel::Empty => "".into(),
+                        EmptySentinel::ZeroHex => "0x".into(),
+                    }
+                } else {
+                    v.to_hex()
+                }
+            }
+            FieldValue::Str(ref v) => v.clone(),
+            FieldValue::Bool(v) => if v { "true".into() } else { "false".into() },
+            FieldValue::Null => match config.empty_sentinel {
+                EmptySentinel::Dot => ".".into(),
+                EmptySentinel::Empty => "".into(),
+                EmptySentinel::ZeroHex => "0x0".into(),
+            },
+        }
+    }
+
+    fn to_json(&self, config: &Config) -> String {
+        match *self {
+            FieldValue::U64(v) => format!("{}", v),
+            FieldValue::Gas(v) => {
+                if config.gas_as_string {
+                    format!("\"{}\"", v)
+                } else {
+                    format!("{}", v)
+                }
+            }
+            FieldValue::I64(v) => format!("{}", v),
+            FieldValue::U256(ref v) => {
+                if config.fixed_width_hex {
+                    format!("\"0x{:0>64}\"", format!("{:x}", v))
+                } else {
+                    format!("\"0x{:x}\"", v)
+                }
+            }
+            FieldValue::Address(ref v) => format!("\"0x{:x}\"", v),
+            FieldValue::H256(ref v) => format!("\"0x{:x}\"", v),
+            FieldValue::Bytes(ref v) => format!("\"0x{}\"", v.to_hex()),
+            FieldValue::Str(ref v) => json_escape(v),
+            FieldValue::Bool(v) => format!("{}", v),
+            FieldValue::Null => "null".into(),
+        }
+    }
+}
+
+/// A typed instrumentation event, rendered to exactly one output line.
+#[derive(Clone, Debug)]
+pub struct Event {
+    name: &'static str,
+    channel: Channel,
+    fields: Vec<(&'static str, FieldValue)>,
+}
+
+impl Event {
+    /// Creates an event emitted on the `DMLOG` channel.
+    pub fn new(name: &'static str) -> Event {
+        Event {
+            name: name,
+            channel: Channel::Log,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Creates an event emitted on the `DMDEBUG` channel.
+    pub fn debug(name: &'static str) -> Event {
+        Event {
+            name: name,
+            channel: Channel::Debug,
+            fields: Vec::new(),
+        }
+    }
+
+    /// The event name, e.g. `UNCLE_REWARD`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The channel this event is emitted on.
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// The category this event belongs to, for sink routing.
+    pub fn category(&self) -> EventCategory {
+        match self.name {
+            "BEGIN_BLOCK" | "END_BLOCK" | "UNCLES" | "UNCLE_HASHES" | "RECEIPTS_ROOT"
+            | "UNCLE_REWARD" | "EXTRA_DATA" | "CONSENSUS_TYPE" | "TRANSACTIONS_LIST"
+            | "BASE_FEE_COMPUTATION" | "SUPPLY_DELTA" | "WITHDRAWAL" | "BLOCK_ADDRESSES"
+            | "BLOCK_CONTRACT_CHANGES" | "FINALITY" | "REORG" => EventCategory::Block,
+            "BEGIN_APPLY_TRX" | "END_APPLY_TRX" | "TRX_FROM" | "BLOB_HASH"
+            | "VALIDATION_COMPLETE" | "FEE_CAP_ANALYSIS" | "PRESTATE" | "POSTSTATE" => {
+                EventCategory::Transaction
+            }
+            "EVM_RUN_CALL" | "EVM_END_CALL" | "EVM_KECCAK" | "SUICIDE_CHANGE" | "EOF_DEPLOY"
+            | "CREATE2_PREIMAGE" | "RETURN_DATA_COPY" | "MCOPY" | "ENV_READ" | "BLOBHASH"
+            | "DEPTH_LIMIT_EXCEEDED" | "REENTRANCY_DETECTED" | "CODE_CHANGE"
+            | "CODE_STORAGE_OUT_OF_GAS" => EventCategory::Call,
+            "BALANCE_CHANGE" | "BALANCE_READ" => EventCategory::Balance,
+            "STORAGE_CHANGE" | "SSTORE_GAS" | "SLOAD_GAS" | "STORAGE_STATS"
+            | "TRANSIENT_STORAGE_CHANGE" | "TSTORE_GAS" | "STORAGE_ROOT_CHANGE"
+            | "SLOT_WARMED" | "READ_SET" | "WRITE_SET" => EventCategory::Storage,
+            "GAS_CHANGE" | "PRECOMPILE_REFUND" | "PRECOMPILE_GAS" | "PRECOMPILE_GAS_TOTAL"
+            | "HASHING_PRECOMPILE_GAS" | "EXP_GAS" | "GAS_OPCODE" | "REFUND_CAP" => {
+                EventCategory::Gas
+            }
+            "ADD_LOG" => EventCategory::Log,
+            _ => EventCategory::Other,
+        }
+    }
+
+    /// The ordered named fields of this event.
+    pub fn fields(&self) -> &[(&'static str, FieldValue)] {
+        &self.fields
+    }
+
+    /// Appends an arbitrary field.
+    pub fn field(mut self, name: &'static str, value: FieldValue) -> Event {
+        self.fields.push((name, value));
+        self
+    }
+
+    /// Appends an unsigned integer field.
+    pub fn u64(self, name: &'static str, value: u64) -> Event {
+        self.field(name, FieldValue::U64(value))
+    }
+
+    /// Appends a gas quantity field.
+    pub fn gas(self, name: &'static str, value: u64) -> Event {
+        self.field(name, FieldValue::Gas(value))
+    }
+
+    /// Appends a signed integer field.
+    pub fn i64(self, name: &'static str, value: i64) -> Event {
+        self.field(name, FieldValue::I64(value))
+    }
+
+    /// Appends an optional unsigned integer field; `None` renders as the
+    /// absent-value sentinel.
+    pub fn opt_u64(self, name: &'static str, value: Option<u64>) -> Event {
+        match value {
+            Some(value) => self.field(name, FieldValue::U64(value)),
+            None => self.field(name, FieldValue::Null),
+        }
+    }
+
+    /// Appends a 256-bit unsigned integer field.
+    pub fn u256(self, name: &'static str, value: &U256) -> Event {
+        self.field(name, FieldValue::U256(*value))
+    }
+
+    /// Appends an address field.
+    pub fn address(self, name: &'static str, value: &Address) -> Event {
+        self.field(name, FieldValue::Address(*value))
+    }
+
+    /// Appends a 256-bit hash field.
+    pub fn h256(self, name: &'static str, value: &H256) -> Event {
+        self.field(name, FieldValue::H256(*value))
+    }
+
+    /// Appends a raw bytes field.
+    pub fn bytes(self, name: &'static str, value: &[u8]) -> Event {
+        self.field(name, FieldValue::Bytes(value.to_vec()))
+    }
+
+    /// Appends a literal string field.
+    pub fn string(self, name: &'static str, value: &str) -> Event {
+        self.field(name, FieldValue::Str(value.to_owned()))
+    }
+
+    /// Appends a boolean field.
+    pub fn bool(self, name: &'static str, value: bool) -> Event {
+        self.field(name, FieldValue::Bool(value))
+    }
+
+    /// Renders the event to a single line according to `config`.
+    pub fn render(&self, config: &Config) -> String {
+        match config.format {
+            Format::Text => self.to_text(config),
+            Format::Json => self.to_json(config),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => self.to_cbor(),
+        }
+    }
+
+    fn to_text(&self, config: &Config) -> String {
+        let mut line = self.name.to_owned();
+        for &(_, ref value) in &self.fields {
+            line.push(config.field_delimiter.0);
+            line.push_str(&value.to_text(config));
+        }
+        line
+    }
+
+    /// Renders the CBOR encoding of the event onto the line-oriented
+    /// stream: the encoded byte length in decimal, a space, then the bytes
+    /// in hex, so line framing and channel prefixes keep working while the
+    /// length prefix lets consumers validate the payload.
+    #[cfg(feature = "cbor")]
+    fn to_cbor(&self) -> String {
+        let encoded = ::cbor::encode(self.name, &self.fields);
+        format!("{} {}", encoded.len(), encoded.to_hex())
+    }
+
+    fn to_json(&self, config: &Config) -> String {
+        let mut line = if config.schema_envelope {
+            format!(
+                "{{\"v\":{},\"type\":{}",
+                SCHEMA_VERSION,
+                json_escape(&self.name.to_lowercase())
+            )
+        } else {
+            format!("{{\"type\":{}", json_escape(&self.name.to_lowercase()))
+        };
+        // A field name repeated on the event (topics, uncle hashes, slot
+        // pairs) renders as one array under that name, at the position of
+        // its first occurrence — RFC 8259 parsers keep only one value per
+        // key, so duplicate keys would silently drop all but the last.
+        let mut rendered: Vec<&str> = Vec::new();
+        for &(name, _) in &self.fields {
+            if rendered.contains(&name) {
+                continue;
+            }
+            rendered.push(name);
+            let values: Vec<String> = self
+                .fields
+                .iter()
+                .filter(|&&(n, _)| n == name)
+                .map(|&(_, ref value)| value.to_json(config))
+                .collect();
+            line.push(',');
+            line.push_str(&json_escape(name));
+            line.push(':');
+            if values.len() == 1 {
+                line.push_str(&values[0]);
+            } else {
+                line.push('[');
+                line.push_str(&values.join(","));
+                line.push(']');
+            }
+        }
+        line.push('}');
+        line
+    }
+}
+
+/// Escapes `value` as a JSON string literal, including the surrounding
+/// quotes.
+pub(crate) fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_encoding_uses_dot_sentinel() {
+        let event = Event::new("TEST")
+            .u64("num", 42)
+            .u256("zero", &U256::zero())
+            .u256("value", &U256::from(0x1234))
+            .bytes("empty", &[])
+            .bytes("data", &[0xab, 0xcd]);
+        assert_eq!(event.render(&Config::default()), "TEST 42 . 1234 . abcd");
+    }
+
+    #[test]
+    fn empty_sentinel_modes_control_zero_rendering() {
+        let event = Event::new("TEST")
+            .u256("zero", &U256::zero())
+            .address("addr", &Address::zero())
+            .bytes("data", &[]);
+
+        for &(sentinel, expected) in &[
+            (EmptySentinel::Dot, "TEST . . ."),
+            (EmptySentinel::Empty, "TEST   "),
+            (EmptySentinel::ZeroHex, "TEST 0x0 0x0 0x"),
+        ] {
+            let config = Config {
+                empty_sentinel: sentinel,
+                ..Default::default()
+            };
+            assert_eq!(event.render(&config), expected);
+        }
+    }
+
+    #[test]
+    fn field_delimiter_is_configurable() {
+        use config::FieldDelimiter;
+
+        let config = Config {
+            field_delimiter: FieldDelimiter('\t'),
+            ..Default::default()
+        };
+        let event = Event::new("TEST")
+            .u64("num", 42)
+            .u256("value", &U256::from(0x1234))
+            .string("reason", "transfer");
+        let line = event.render(&config);
+        assert_eq!(line, "TEST\t42\t1234\ttransfer");
+
+        // Splitting on the delimiter recovers exactly the fields.
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(fields, vec!["TEST", "42", "1234", "transfer"]);
+    }
+
+    #[test]
+    fn fixed_width_hex_pads_to_full_width() {
+        let config = Config {
+            fixed_width_hex: true,
+            ..Default::default()
+        };
+        let event = Event::new("TEST")
+            .u256("value", &U256::from(0x1234))
+            .u256("zero", &U256::zero())
+            .address("addr", &Address::zero());
+        assert_eq!(
+            event.render(&config),
+            format!("TEST {:0>64} {:0>64} {:0>40}", "1234", "0", "0")
+        );
+    }
+
+    #[test]
+    fn fixed_width_hex_pads_json_values_too() {
+        let config = Config {
+            format: Format::Json,
+            fixed_width_hex: true,
+            ..Default::default()
+        };
+        let event = Event::new("TEST").u256("value", &U256::from(0x1234));
+        assert_eq!(
+            event.render(&config),
+            format!("{{\"type\":\"test\",\"value\":\"0x{:0>64}\"}}", "1234")
+        );
+    }
+
+    #[test]
+    fn json_encoding_groups_repeated_fields_into_arrays() {
+        let config = Config {
+            format: Format::Json,
+            ..Default::default()
+        };
+        // A two-topic log: the repeated `topic` field must become one
+        // array, not duplicate object keys a JSON parser would collapse.
+        let event = Event::new("ADD_LOG")
+            .u64("call_index", 1)
+            .u64("topic_count", 2)
+            .h256("topic", &H256::from_low_u64_be(0xaa))
+            .h256("topic", &H256::from_low_u64_be(0xbb))
+            .bytes("data", &[0x01]);
+        assert_eq!(
+            event.render(&config),
+            format!(
+                "{{\"type\":\"add_log\",\"call_index\":1,\"topic_count\":2,\
+                 \"topic\":[\"0x{:x}\",\"0x{:x}\"],\"data\":\"0x01\"}}",
+                H256::from_low_u64_be(0xaa),
+                H256::from_low_u64_be(0xbb)
+            )
+        );
+    }
+
+    #[test]
+    fn schema_envelope_carries_the_schema_version() {
+        let config = Config {
+            format: Format::Json,
+            schema_envelope: true,
+            ..Default::default()
+        };
+        let event = Event::new("STORAGE_CHANGE").u64("call_index", 1);
+        assert_eq!(
+            event.render(&config),
+            format!("{{\"v\":{},\"type\":\"storage_change\",\"call_index\":1}}", SCHEMA_VERSION)
+        );
+    }
+
+    #[test]
+    fn json_encoding_prefixes_hex_and_keeps_field_order() {
+        let config = Config {
+            format: Format::Json,
+            ..Default::default()
+        };
+        let event = Event::new("TEST").u64("num", 42).u256("value", &U256::from(0x1234));
+        assert_eq!(
+            event.render(&config),
+            "{\"type\":\"test\",\"num\":42,\"value\":\"0x1234\"}"
+        );
+    }
+}
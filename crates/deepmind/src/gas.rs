@@ -0,0 +1,201 @@
+// Copyright 2015-2020 Parity Technologies (UK) Ltd.
+// This file is part of OpenEthereum.
+
+// OpenEthereum is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// OpenEthereum is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with OpenEthereum.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Reasons attached to gas and balance change events.
+
+/// Forks that changed gas pricing, ordered by activation. Only the forks
+/// that matter for the costs the stream reports are listed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Fork {
+    /// Launch rules.
+    Frontier,
+    /// EIP-150 gas repricing (Tangerine Whistle).
+    Tangerine,
+    /// EIP-1884 repricing of trie-dependent opcodes.
+    Istanbul,
+    /// EIP-2929 cold/warm access pricing.
+    Berlin,
+    /// EIP-1559 and EIP-3529 refund changes.
+    London,
+    /// Withdrawals, EIP-3855/3860.
+    Shanghai,
+    /// Transient storage, blobs, MCOPY.
+    Cancun,
+}
+
+impl Fork {
+    /// The stable string identifying this fork on the stream.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Fork::Frontier => "frontier",
+            Fork::Tangerine => "tangerine",
+            Fork::Istanbul => "istanbul",
+            Fork::Berlin => "berlin",
+            Fork::London => "london",
+            Fork::Shanghai => "shanghai",
+            Fork::Cancun => "cancun",
+        }
+    }
+
+    /// The SELFDESTRUCT base cost under this fork's schedule: free at
+    /// launch, 5000 from EIP-150. The EIP-2929 cold-beneficiary surcharge
+    /// of 2600 is not folded in here — it is charged and attributed
+    /// separately so consumers can tell base cost from access cost.
+    pub fn selfdestruct_cost(self) -> u64 {
+        if self >= Fork::Tangerine {
+            5000
+        } else {
+            0
+        }
+    }
+
+    /// The SLOAD cost under this fork's schedule: 50 at launch, 200 from
+    /// EIP-150, 800 from EIP-1884, and 2100 cold / 100 warm from EIP-2929.
+    /// `cold` is ignored before Berlin, where every access costs the same.
+    pub fn sload_cost(self, cold: bool) -> u64 {
+        if self >= Fork::Berlin {
+            if cold {
+                2100
+            } else {
+                100
+            }
+        } else if self >= Fork::Istanbul {
+            800
+        } else if self >= Fork::Tangerine {
+            200
+        } else {
+            50
+        }
+    }
+}
+
+/// Why a `GAS_CHANGE` event was recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GasChangeReason {
+    /// Base transaction cost plus calldata cost, charged before execution.
+    IntrinsicGas,
+    /// Gas forwarded to a CALL.
+    Call,
+    /// Gas forwarded to a CALLCODE.
+    CallCode,
+    /// Gas forwarded to a DELEGATECALL.
+    DelegateCall,
+    /// Gas forwarded to a STATICCALL.
+    StaticCall,
+    /// Storage of deployed contract code, 200 gas per byte. The legacy
+    /// undifferentiated reason; the deployment path now attributes the
+    /// deposit to [`GasChangeReason::DeploymentCodeStorage`], keeping this
+    /// for embedders that cannot tell the RETURN context apart.
+    CodeStorage,
+    /// Storage of the code provided by the RETURN ending a deployment
+    /// frame, 200 gas per byte. Distinguished from a plain call's RETURN,
+    /// which only sets the caller's return buffer and carries no storage
+    /// cost.
+    DeploymentCodeStorage,
+    /// Refund counter credited back to the sender after execution.
+    RefundAfterExecution,
+    /// Unspent gas returned to the caller when a frame completes.
+    CallLeftOver,
+    /// RETURNDATACOPY copying from the last call's return buffer,
+    /// 3 gas base plus 3 per word copied.
+    ReturnDataCopy,
+    /// BALANCE of an account not yet in the accessed-addresses set,
+    /// 2600 gas (EIP-2929).
+    BalanceColdAccess,
+    /// SELFBALANCE reading the executing account's balance, a flat 5 gas
+    /// (EIP-1884) with no cold-access penalty.
+    SelfBalance,
+    /// TLOAD reading a transient storage slot, a flat 100 gas (EIP-1153).
+    /// Never attributed to persistent storage gas.
+    TransientStorageLoad,
+    /// TSTORE writing a transient storage slot, a flat 100 gas (EIP-1153)
+    /// with none of the SSTORE branches and no refund interaction.
+    TransientStorageStore,
+    /// SELFDESTRUCT base cost, fork-dependent (see
+    /// [`Fork::selfdestruct_cost`]).
+    SelfDestruct,
+    /// SELFDESTRUCT sending to a beneficiary not yet in the
+    /// accessed-addresses set, 2600 gas (EIP-2929). Kept apart from the
+    /// base cost so access pricing stays visible.
+    ColdBeneficiaryAccess,
+}
+
+impl GasChangeReason {
+    /// The stable string identifying this reason on the stream.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GasChangeReason::IntrinsicGas => "intrinsic_gas",
+            GasChangeReason::Call => "call",
+            GasChangeReason::CallCode => "call_code",
+            GasChangeReason::DelegateCall => "delegate_call",
+            GasChangeReason::StaticCall => "static_call",
+            GasChangeReason::CodeStorage => "code_storage",
+            GasChangeReason::DeploymentCodeStorage => "deployment_code_storage",
+            GasChangeReason::RefundAfterExecution => "refund_after_execution",
+            GasChangeReason::CallLeftOver => "call_left_over",
+            GasChangeReason::ReturnDataCopy => "return_data_copy",
+            GasChangeReason::BalanceColdAccess => "balance_cold_access",
+            GasChangeReason::SelfBalance => "self_balance",
+            GasChangeReason::TransientStorageLoad => "transient_storage_load",
+            GasChangeReason::TransientStorageStore => "transient_storage_store",
+            GasChangeReason::SelfDestruct => "self_destruct",
+            GasChangeReason::ColdBeneficiaryAccess => "cold_beneficiary_access",
+        }
+    }
+}
+
+/// Why a `BALANCE_CHANGE` event was recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BalanceChangeReason {
+    /// Value moved by a transaction or a call.
+    Transfer,
+    /// Sender buying gas up-front at transaction start.
+    GasBuy,
+    /// Unused gas refunded to the sender at transaction end.
+    GasRefund,
+    /// Block reward credited to the author.
+    RewardMineBlock,
+    /// Uncle inclusion reward credited to the uncle author.
+    RewardMineUncle,
+    /// Transaction fees credited to the author.
+    RewardTransactionFee,
+    /// Balance of a self-destructed account credited to the beneficiary.
+    SuicideRefund,
+    /// Balance of a self-destructed account wiped from the state.
+    SuicideWithdraw,
+    /// Value destroyed, e.g. the burnt base fee.
+    Burn,
+    /// Beacon chain withdrawal credited at block level (EIP-4895).
+    Withdrawal,
+}
+
+impl BalanceChangeReason {
+    /// The stable string identifying this reason on the stream.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BalanceChangeReason::Transfer => "transfer",
+            BalanceChangeReason::GasBuy => "gas_buy",
+            BalanceChangeReason::GasRefund => "gas_refund",
+            BalanceChangeReason::RewardMineBlock => "reward_mine_block",
+            BalanceChangeReason::RewardMineUncle => "reward_mine_uncle",
+            BalanceChangeReason::RewardTransactionFee => "reward_transaction_fee",
+            BalanceChangeReason::SuicideRefund => "suicide_refund",
+            BalanceChangeReason::SuicideWithdraw => "suicide_withdraw",
+            BalanceChangeReason::Burn => "burn",
+            BalanceChangeReason::Withdrawal => "withdrawal",
+        }
+    }
+}
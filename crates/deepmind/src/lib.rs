@@ -0,0 +1,48 @@
+// Copyright 2015-2020 Parity Technologies (UK) Ltd.
+// This file is part of OpenEthereum.
+
+// OpenEthereum is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// OpenEthereum is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with OpenEthereum.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Deep Mind instrumentation.
+//!
+//! Emits a line-oriented stream of block, transaction and EVM level events
+//! that external consumers (e.g. a Firehose reader attached to the node's
+//! standard output) ingest to reconstruct the chain's full state transition.
+//! Protocol data is printed on the `DMLOG` channel; diagnostics meant for
+//! developers of the instrumentation itself go to the `DMDEBUG` channel.
+
+extern crate ethereum_types;
+extern crate keccak_hash;
+extern crate parking_lot;
+extern crate rustc_hex;
+
+pub mod eth;
+
+#[cfg(feature = "cbor")]
+mod cbor;
+mod config;
+mod context;
+mod event;
+mod gas;
+mod printer;
+mod tracer;
+
+pub use self::{
+    config::{BlockAddressDetail, Config, EmptySentinel, FieldDelimiter, Format, UncleDetail},
+    context::{BlockContext, ChainSpec, Clock, Context},
+    event::{Event, EventCategory, FieldValue, SCHEMA_VERSION},
+    gas::{BalanceChangeReason, Fork, GasChangeReason},
+    printer::{Channel, ChannelPrinter, IoPrinter, MemoryPrinter, Printer},
+    tracer::{CallKind, NoopTracer, Tracer, TransactionTracer},
+};
@@ -0,0 +1,212 @@
+// Copyright 2015-2020 Parity Technologies (UK) Ltd.
+// This file is part of OpenEthereum.
+
+// OpenEthereum is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// OpenEthereum is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with OpenEthereum.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Output sinks for rendered instrumentation lines.
+
+use parking_lot::Mutex;
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+};
+
+/// The channel an instrumentation line is emitted on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// Protocol data consumed by downstream tooling, prefixed `DMLOG`.
+    Log,
+    /// Developer diagnostics, prefixed `DMDEBUG`.
+    Debug,
+}
+
+impl Channel {
+    /// The line prefix identifying this channel on the stream.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Channel::Log => "DMLOG",
+            Channel::Debug => "DMDEBUG",
+        }
+    }
+}
+
+/// Sink for rendered instrumentation lines.
+pub trait Printer: Send + Sync {
+    /// Writes a single rendered line on the given channel.
+    fn print(&self, channel: Channel, line: &str);
+}
+
+/// Printer writing prefixed lines to an `io::Write` (standard output in
+/// production). Lines are flushed eagerly so an attached reader never waits
+/// on a partially buffered event.
+pub struct IoPrinter<W: io::Write + Send> {
+    out: Mutex<W>,
+}
+
+impl<W: io::Write + Send> IoPrinter<W> {
+    /// Creates a printer writing to `out`.
+    pub fn new(out: W) -> Self {
+        IoPrinter {
+            out: Mutex::new(out),
+        }
+    }
+}
+
+impl<W: io::Write + Send> Printer for IoPrinter<W> {
+    fn print(&self, channel: Channel, line: &str) {
+        let mut out = self.out.lock();
+        let _ = writeln!(out, "{} {}", channel.prefix(), line);
+        let _ = out.flush();
+    }
+}
+
+/// Printer decoupling the emitting thread from a slow consumer through a
+/// bounded queue. A line that does not fit is dropped rather than stalling
+/// block import, and the next line that does fit is preceded by a
+/// `THROTTLED <count>` line on the `DMDEBUG` channel carrying the number
+/// of lines lost since the last signal — an explicit loss marker instead
+/// of a silent gap. The embedder drains the queue to the wrapped printer
+/// by calling [`ChannelPrinter::pump`] from its output thread.
+pub struct ChannelPrinter {
+    inner: Arc<dyn Printer>,
+    sender: mpsc::SyncSender<(Channel, String)>,
+    receiver: Mutex<mpsc::Receiver<(Channel, String)>>,
+    /// Lines dropped since the last `THROTTLED` signal made it out.
+    dropped: AtomicU64,
+}
+
+impl ChannelPrinter {
+    /// Creates a printer queueing up to `capacity` lines in front of
+    /// `inner`.
+    pub fn new(inner: Arc<dyn Printer>, capacity: usize) -> ChannelPrinter {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        ChannelPrinter {
+            inner: inner,
+            sender: sender,
+            receiver: Mutex::new(receiver),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Drains every currently queued line to the wrapped printer.
+    pub fn pump(&self) {
+        let receiver = self.receiver.lock();
+        while let Ok((channel, line)) = receiver.try_recv() {
+            self.inner.print(channel, &line);
+        }
+    }
+}
+
+impl Printer for ChannelPrinter {
+    fn print(&self, channel: Channel, line: &str) {
+        // A pending drop count goes out before the line so the signal sits
+        // exactly where the gap is; if even the signal does not fit, the
+        // count keeps accumulating (including this line) for the next try.
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            let signal = (Channel::Debug, format!("THROTTLED {}", dropped));
+            if self.sender.try_send(signal).is_err() {
+                self.dropped.fetch_add(dropped + 1, Ordering::Relaxed);
+                return;
+            }
+        }
+        if self.sender.try_send((channel, line.to_owned())).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Printer collecting lines in memory, for tests and embedders that
+/// post-process the stream.
+#[derive(Default)]
+pub struct MemoryPrinter {
+    lines: Mutex<Vec<(Channel, String)>>,
+}
+
+impl MemoryPrinter {
+    /// Creates an empty collecting printer.
+    pub fn new() -> Self {
+        MemoryPrinter::default()
+    }
+
+    /// All collected lines, in emission order, including the channel prefix.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines
+            .lock()
+            .iter()
+            .map(|&(channel, ref line)| format!("{} {}", channel.prefix(), line))
+            .collect()
+    }
+
+    /// Collected lines of a single channel, in emission order, without the
+    /// channel prefix.
+    pub fn lines_on(&self, channel: Channel) -> Vec<String> {
+        self.lines
+            .lock()
+            .iter()
+            .filter(|&&(c, _)| c == channel)
+            .map(|&(_, ref line)| line.clone())
+            .collect()
+    }
+}
+
+impl Printer for MemoryPrinter {
+    fn print(&self, channel: Channel, line: &str) {
+        self.lines.lock().push((channel, line.to_owned()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_printer_signals_dropped_lines() {
+        let inner = Arc::new(MemoryPrinter::new());
+        let printer = ChannelPrinter::new(inner.clone(), 2);
+
+        // Four lines into a two-slot queue with nothing draining it: the
+        // last two are dropped instead of blocking the emitter.
+        for i in 0..4 {
+            printer.print(Channel::Log, &format!("EVENT {}", i));
+        }
+        printer.pump();
+        assert_eq!(
+            inner.lines(),
+            vec!["DMLOG EVENT 0".to_owned(), "DMLOG EVENT 1".to_owned()]
+        );
+
+        // The next line that fits is preceded by the loss signal carrying
+        // the exact drop count, placed where the gap is.
+        printer.print(Channel::Log, "EVENT 4");
+        printer.pump();
+        assert_eq!(
+            inner.lines()[2..],
+            ["DMDEBUG THROTTLED 2".to_owned(), "DMLOG EVENT 4".to_owned()]
+        );
+    }
+
+    #[test]
+    fn channel_printer_is_silent_without_drops() {
+        let inner = Arc::new(MemoryPrinter::new());
+        let printer = ChannelPrinter::new(inner.clone(), 8);
+        printer.print(Channel::Log, "EVENT 0");
+        printer.pump();
+
+        assert_eq!(inner.lines(), vec!["DMLOG EVENT 0".to_owned()]);
+    }
+}
@@ -0,0 +1,3058 @@
+// Copyright 2015-2020 Parity Technologies (UK) Ltd.
+// This file is part of OpenEthereum.
+
+// OpenEthereum is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// OpenEthereum is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with OpenEthereum.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Transaction and EVM level instrumentation.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    mem,
+    sync::Arc,
+};
+
+use keccak_hash::keccak;
+use rustc_hex::ToHex;
+
+use context::{BlockState, Context};
+use eth;
+use event::{Event, FieldValue};
+use gas::{BalanceChangeReason, Fork, GasChangeReason};
+
+/// The kind of call frame being entered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallKind {
+    /// Plain CALL.
+    Call,
+    /// CALLCODE.
+    CallCode,
+    /// DELEGATECALL.
+    DelegateCall,
+    /// STATICCALL.
+    StaticCall,
+    /// Contract creation through CREATE or a creation transaction.
+    Create,
+    /// Contract creation through CREATE2.
+    Create2,
+}
+
+impl CallKind {
+    /// The stable string identifying this call kind on the stream.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CallKind::Call => "CALL",
+            CallKind::CallCode => "CALLCODE",
+            CallKind::DelegateCall => "DELEGATE",
+            CallKind::StaticCall => "STATIC",
+            CallKind::Create => "CREATE",
+            CallKind::Create2 => "CREATE2",
+        }
+    }
+
+    /// The call type string Geth's `callTracer` uses for this kind. Differs
+    /// from [`CallKind::as_str`] only in spelling the delegate and static
+    /// variants out in full.
+    pub fn geth_str(self) -> &'static str {
+        match self {
+            CallKind::DelegateCall => "DELEGATECALL",
+            CallKind::StaticCall => "STATICCALL",
+            _ => self.as_str(),
+        }
+    }
+
+    /// The gas change reason the base cost of this call opcode must be
+    /// attributed to, so callers cannot pair a variant with the wrong
+    /// reason. `None` for the create family, whose cost is attributed
+    /// through the creation-specific reasons.
+    pub fn base_gas_reason(self) -> Option<GasChangeReason> {
+        match self {
+            CallKind::Call => Some(GasChangeReason::Call),
+            CallKind::CallCode => Some(GasChangeReason::CallCode),
+            CallKind::DelegateCall => Some(GasChangeReason::DelegateCall),
+            CallKind::StaticCall => Some(GasChangeReason::StaticCall),
+            CallKind::Create | CallKind::Create2 => None,
+        }
+    }
+}
+
+/// EVM-facing instrumentation events, recorded by the executive while it
+/// applies a transaction.
+pub trait Tracer: Send {
+    /// Records entry into a new call frame. The tracer assigns and tracks
+    /// call indexes internally.
+    fn start_call(
+        &mut self,
+        kind: CallKind,
+        from: &eth::Address,
+        to: &eth::Address,
+        value: &eth::U256,
+        gas_limit: u64,
+        input: &[u8],
+    );
+
+    /// Records completion of the innermost active call frame.
+    fn end_call(&mut self, gas_left: u64, return_data: &[u8]);
+
+    /// Marks the innermost active frame as reverted, discarding its pending
+    /// poststate changes and those of its completed children. Called before
+    /// the frame's `end_call`.
+    fn record_revert(&mut self);
+
+    /// Records a call that failed because it would exceed the 1024 frame
+    /// depth limit. No frame is entered: the failure is attributed to the
+    /// frame that attempted the call, and is distinct from out-of-gas.
+    fn record_depth_limit_exceeded(&mut self);
+
+    /// Records a balance change on `address` attributed to `reason`.
+    fn record_balance_change(
+        &mut self,
+        address: &eth::Address,
+        old: &eth::U256,
+        new: &eth::U256,
+        reason: BalanceChangeReason,
+    );
+
+    /// Records gas consumed or credited, attributed to `reason`.
+    fn record_gas_change(&mut self, old: u64, new: u64, reason: GasChangeReason);
+
+    /// Records a storage slot change on `address`.
+    fn record_storage_change(
+        &mut self,
+        address: &eth::Address,
+        key: &eth::H256,
+        old: &eth::H256,
+        new: &eth::H256,
+    );
+
+    /// Records an SLOAD of `key` on `address`. Feeds the transaction's
+    /// storage statistics; emits nothing by itself.
+    fn record_storage_read(&mut self, address: &eth::Address, key: &eth::H256);
+
+    /// Records the gas charged by one SLOAD execution under the rules of
+    /// the active `fork`, so historical replay attributes fork-accurate
+    /// costs (50 at launch, 200 from Tangerine, 800 from Istanbul,
+    /// 2100 cold / 100 warm from Berlin). The cost is derived from the
+    /// fork, not caller-provided, so it cannot drift from the schedule;
+    /// `cold` only matters from Berlin on. Also feeds the storage
+    /// statistics like `record_storage_read`.
+    fn record_sload_gas(&mut self, address: &eth::Address, key: &eth::H256, fork: Fork, cold: bool);
+
+    /// Records a TSTORE (EIP-1153) of `key` on `address`. Transient slots
+    /// are discarded at the end of the transaction, so these changes are
+    /// kept apart from `STORAGE_CHANGE` and excluded from the storage
+    /// statistics; the accompanying flat 100 gas is attributed to
+    /// `GasChangeReason::TransientStorageStore`, never to an SSTORE reason.
+    fn record_transient_storage_change(
+        &mut self,
+        address: &eth::Address,
+        key: &eth::H256,
+        old: &eth::H256,
+        new: &eth::H256,
+    );
+
+    /// Records the gas metering of one SSTORE execution (EIP-2200 has
+    /// several branches depending on `original`/`current`/`new`), with the
+    /// charged `gas_cost` and the change applied to the refund counter.
+    fn record_sstore_gas(
+        &mut self,
+        address: &eth::Address,
+        key: &eth::H256,
+        original: &eth::H256,
+        current: &eth::H256,
+        new: &eth::H256,
+        gas_cost: u64,
+        refund_delta: i64,
+    );
+
+    /// Records the gas metering of one TSTORE execution: the flat
+    /// `gas_cost` (100 gas, EIP-1153), with deliberately no refund field —
+    /// transient storage grants no refunds, unlike SSTORE. `refund_delta`
+    /// exists purely as an invariant check: any non-zero value is an
+    /// integration bug, reported as a `TRANSIENT_REFUND_VIOLATION` warning
+    /// on the `DMDEBUG` channel and kept off the protocol stream.
+    #[track_caller]
+    fn record_transient_storage_gas(
+        &mut self,
+        address: &eth::Address,
+        key: &eth::H256,
+        gas_cost: u64,
+        refund_delta: i64,
+    );
+
+    /// Records the storage of `code` returned by a finished deployment at
+    /// `address`, with `gas_left` as the frame stood before paying the
+    /// 200-gas-per-byte deposit. Emits a `CODE_CHANGE` carrying the code
+    /// and the deposit charged, followed by the matching
+    /// `GasChangeReason::DeploymentCodeStorage` gas change; a frame that cannot
+    /// afford the deposit emits `CODE_STORAGE_OUT_OF_GAS` instead and the
+    /// creation fails.
+    fn record_contract_deployed(&mut self, address: &eth::Address, code: &[u8], gas_left: u64);
+
+    /// Records a log emitted by `address`.
+    fn record_log(&mut self, address: &eth::Address, topics: &[eth::H256], data: &[u8]);
+
+    /// Records a SELFDESTRUCT of `address` sending its remaining `balance`
+    /// to `beneficiary`. A self-referential beneficiary is flagged as a
+    /// self-burn: the balance is destroyed with the account (pre-EIP-6780),
+    /// not transferred.
+    fn record_selfdestruct(
+        &mut self,
+        address: &eth::Address,
+        beneficiary: &eth::Address,
+        balance: &eth::U256,
+    );
+
+    /// Records the gas charged by one SELFDESTRUCT execution under the
+    /// rules of the active `fork`: the fork-correct base cost (free until
+    /// EIP-150, 5000 after) attributed to `GasChangeReason::SelfDestruct`,
+    /// and, from Berlin on when the beneficiary is cold, a separate
+    /// 2600-gas `GasChangeReason::ColdBeneficiaryAccess` change. A fork
+    /// whose base cost is zero records nothing for the base. A `gas_left`
+    /// below a charge is an integration bug — the EVM fails such a
+    /// SELFDESTRUCT before metering it — reported as a
+    /// `SELFDESTRUCT_GAS_UNDERFLOW` warning on the `DMDEBUG` channel
+    /// instead of underflowing.
+    #[track_caller]
+    fn record_selfdestruct_gas(&mut self, gas_left: u64, fork: Fork, cold_beneficiary: bool);
+
+    /// Records a gas refund granted by the precompile at `address`. No
+    /// current precompile grants refunds; this extension point exists so a
+    /// future refunding precompile needs no protocol change.
+    fn record_precompile_refund(&mut self, address: &eth::Address, refund: u64);
+
+    /// Records `gas_cost` charged by the precompile at `address`. Also
+    /// accumulated into the transaction's `PRECOMPILE_GAS_TOTAL` summary,
+    /// separating precompile gas from EVM execution gas for profiling.
+    fn record_precompile_gas(&mut self, address: &eth::Address, gas_cost: u64);
+
+    /// Records one execution of a length-priced hashing precompile —
+    /// SHA-256 at `0x2` (60 gas plus 12 per word), RIPEMD-160 at `0x3`
+    /// (600 plus 120 per word) or the identity copy at `0x4` (15 plus 3
+    /// per word) — carrying the input length and the gas derived from it,
+    /// so consumers can verify the per-word formula. The cost is derived
+    /// here, not caller-provided, so it cannot drift from the schedule; it
+    /// also feeds `PRECOMPILE_GAS_TOTAL`. No-op for any other address.
+    fn record_hashing_precompile_gas(&mut self, address: &eth::Address, input_length: u64);
+
+    /// Records a KECCAK256 opcode execution producing `hash` over `data`.
+    /// The gas is split between `hashing_gas` (30 base plus 6 per word of
+    /// input) and `memory_expansion_gas` (growing memory to cover the input
+    /// region, zero when already covered), so consumers summing either
+    /// component never double-count.
+    fn record_keccak(
+        &mut self,
+        hash: &eth::H256,
+        data: &[u8],
+        hashing_gas: u64,
+        memory_expansion_gas: u64,
+    );
+
+    /// Records a balance read of `address` by the BALANCE or SELFBALANCE
+    /// opcode. `cold` marks the first access of the transaction (EIP-2929),
+    /// charged through `record_gas_change` with
+    /// `GasChangeReason::BalanceColdAccess`; `self_balance` marks a
+    /// SELFBALANCE read (EIP-1884), a flat 5 gas with no cold-access
+    /// penalty, attributed to `GasChangeReason::SelfBalance`.
+    fn record_balance_read(
+        &mut self,
+        address: &eth::Address,
+        balance: &eth::U256,
+        cold: bool,
+        self_balance: bool,
+    );
+
+    /// Records a RETURNDATACOPY execution with the copied `size` in bytes,
+    /// so consumers can verify the 3-gas base plus 3-per-word copy cost.
+    fn record_return_data_copy(&mut self, size: u64, gas_cost: u64);
+
+    /// Records a GAS opcode execution with the remaining gas the contract
+    /// observed (after the opcode's own 2-gas base cost). Gas-forwarding
+    /// patterns branch on this value, so consumers analysing gas-dependent
+    /// control flow need it; nothing else does, so it is emitted only when
+    /// `Config::opcode_level` is enabled.
+    fn record_gas_opcode(&mut self, observed_gas: u64);
+
+    /// Records a BLOBHASH (EIP-4844, Cancun) execution reading the blob
+    /// versioned hash at `index` for a flat 3 gas. An index beyond the
+    /// transaction's blob list pushes zero on the stack, recorded here as
+    /// the zero hash so consumers see the out-of-range read explicitly.
+    /// Emitted only when `Config::opcode_level` is enabled; never called
+    /// on pre-Cancun forks, where the opcode does not exist.
+    fn record_blobhash_opcode(&mut self, index: u64, versioned_hash: &eth::H256);
+
+    /// Records a BLOBBASEFEE (EIP-7516, Cancun) execution reading the
+    /// block's blob base fee for a flat 2 gas, as an `ENV_READ` of
+    /// `blob_base_fee` carrying the value the contract observed — the
+    /// companion of BASEFEE for the blob fee market. Never called on
+    /// pre-Cancun forks, where the opcode does not exist.
+    fn record_blob_base_fee_read(&mut self, value: &eth::U256);
+
+    /// Records an EXP execution with the byte length of its exponent, so
+    /// consumers can verify the 10-gas base plus 50 per exponent byte
+    /// (EIP-160 pricing).
+    fn record_exp_gas(&mut self, exponent_bytes: u64, gas_cost: u64);
+
+    /// Records an MCOPY (EIP-5656, Cancun) execution copying `size` bytes
+    /// between memory regions, with the charged `gas_cost` (3-gas base plus
+    /// 3 per word plus any memory expansion). Never called on pre-Cancun
+    /// forks, where the opcode does not exist.
+    fn record_mcopy(&mut self, dst_offset: u64, src_offset: u64, size: u64, gas_cost: u64);
+
+    /// Records the time spent on JUMPDEST analysis of the code about to be
+    /// executed. Not protocol data (the analysis has no gas cost), purely a
+    /// profiling aid, so it goes to the `DMDEBUG` channel.
+    #[track_caller]
+    fn record_code_analysis(&mut self, code_size: u64, analysis_ns: u64);
+
+    /// Records the section layout of a deployed EOF (EIP-3540) container.
+    /// No-op when `code` is legacy bytecode without the `0xEF00` magic.
+    fn record_eof_deploy(&mut self, code: &[u8]);
+
+    /// Records the EIP-1014 address preimage of a CREATE2 deployment:
+    /// `derived_address` is the last 20 bytes of
+    /// `keccak(0xff ++ sender ++ salt ++ init_code_hash)`, letting consumers
+    /// verify the derivation independently.
+    fn record_create2_preimage(
+        &mut self,
+        sender: &eth::Address,
+        salt: &eth::H256,
+        init_code_hash: &eth::H256,
+        derived_address: &eth::Address,
+    );
+}
+
+/// Gas charged per byte of deployed contract code (G_codedeposit).
+const CODE_DEPOSIT_GAS_PER_BYTE: u64 = 200;
+
+/// The address a creation transaction deploys to: the last 20 bytes of
+/// `keccak(rlp([sender, nonce]))`. The RLP is built by hand here — a
+/// two-item list of the 20-byte sender and the minimal big-endian nonce —
+/// rather than pulling in an RLP dependency for one fixed shape.
+fn create_address(sender: &eth::Address, nonce: u64) -> eth::Address {
+    let mut payload = Vec::with_capacity(30);
+    payload.push(0x80 + 20);
+    payload.extend_from_slice(sender.as_bytes());
+    if nonce == 0 {
+        payload.push(0x80);
+    } else if nonce < 0x80 {
+        payload.push(nonce as u8);
+    } else {
+        let raw = nonce.to_be_bytes();
+        let start = raw.iter().position(|b| *b != 0).unwrap_or(raw.len());
+        payload.push(0x80 + (raw.len() - start) as u8);
+        payload.extend_from_slice(&raw[start..]);
+    }
+    let mut encoded = Vec::with_capacity(payload.len() + 1);
+    encoded.push(0xc0 + payload.len() as u8);
+    encoded.extend_from_slice(&payload);
+    eth::Address::from_slice(&keccak(&encoded).as_bytes()[12..])
+}
+
+/// One state change pending inclusion in the `POSTSTATE` diff, journalled
+/// so reverted frames can discard theirs.
+enum StateChange {
+    Balance(eth::Address, eth::U256),
+    Storage(eth::Address, eth::H256, eth::H256),
+}
+
+/// The base and per-word gas of the length-priced hashing precompiles, or
+/// `None` for an address that is not one of them.
+fn hashing_precompile_schedule(address: &eth::Address) -> Option<(u64, u64)> {
+    if address.as_bytes()[..19] != [0u8; 19][..] {
+        return None;
+    }
+    match address.as_bytes()[19] {
+        2 => Some((60, 12)),
+        3 => Some((600, 120)),
+        4 => Some((15, 3)),
+        _ => None,
+    }
+}
+
+/// One call frame buffered for the `CALL_TRACE` line, only fed when
+/// `Config::call_tracer` is enabled.
+struct TraceFrame {
+    kind: CallKind,
+    from: eth::Address,
+    to: eth::Address,
+    value: eth::U256,
+    gas_limit: u64,
+    gas_used: u64,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    calls: Vec<TraceFrame>,
+}
+
+/// Renders a call frame and its children as a JSON object in the shape of
+/// Geth's `callTracer`: quantities as minimal `0x` hex, `output` omitted
+/// when empty and `calls` omitted when the frame made none, matching what
+/// Geth emits.
+fn render_call_trace(frame: &TraceFrame) -> String {
+    let mut json = format!(
+        "{{\"type\":\"{}\",\"from\":\"0x{:x}\",\"to\":\"0x{:x}\",\"value\":\"0x{:x}\",\"gas\":\"0x{:x}\",\"gasUsed\":\"0x{:x}\",\"input\":\"0x{}\"",
+        frame.kind.geth_str(),
+        frame.from,
+        frame.to,
+        frame.value,
+        frame.gas_limit,
+        frame.gas_used,
+        frame.input.to_hex(),
+    );
+    if !frame.output.is_empty() {
+        json.push_str(&format!(",\"output\":\"0x{}\"", frame.output.to_hex()));
+    }
+    if !frame.calls.is_empty() {
+        let children: Vec<String> = frame.calls.iter().map(render_call_trace).collect();
+        json.push_str(&format!(",\"calls\":[{}]", children.join(",")));
+    }
+    json.push('}');
+    json
+}
+
+/// Section layout of an EOF (EIP-3540) container.
+struct EofLayout {
+    version: u64,
+    code_sections: u64,
+    data_section_size: u64,
+}
+
+/// Best-effort parse of an EOF container header: the `0xEF00` magic, a
+/// version byte, then `(kind, u16 size)` section headers up to the `0`
+/// terminator. Returns `None` for legacy bytecode or a malformed header.
+fn parse_eof_layout(code: &[u8]) -> Option<EofLayout> {
+    if code.len() < 3 || code[0] != 0xef || code[1] != 0x00 {
+        return None;
+    }
+    let mut layout = EofLayout {
+        version: u64::from(code[2]),
+        code_sections: 0,
+        data_section_size: 0,
+    };
+    let mut offset = 3;
+    loop {
+        let kind = *code.get(offset)?;
+        if kind == 0 {
+            return Some(layout);
+        }
+        let size = u64::from(*code.get(offset + 1)?) << 8 | u64::from(*code.get(offset + 2)?);
+        match kind {
+            1 => layout.code_sections += 1,
+            2 => layout.data_section_size += size,
+            _ => {}
+        }
+        offset += 3;
+    }
+}
+
+/// Records the events of a single transaction, assigning a stable index to
+/// every call frame.
+pub struct TransactionTracer {
+    ctx: Arc<Context>,
+    block: Arc<BlockState>,
+    next_call_index: u64,
+    /// Index and target address of every active call frame, innermost last.
+    call_stack: Vec<(u64, eth::Address)>,
+    /// Events held back until the end of the transaction when
+    /// `Config::sort_by_call_index` is enabled, empty otherwise.
+    buffer: Vec<Event>,
+    /// Unique storage slots read so far, for the `STORAGE_STATS` summary.
+    read_slots: HashSet<(eth::Address, eth::H256)>,
+    /// Unique storage slots written so far, for the `STORAGE_STATS` summary.
+    written_slots: HashSet<(eth::Address, eth::H256)>,
+    /// Gas charged by precompiles so far, for the `PRECOMPILE_GAS_TOTAL`
+    /// summary.
+    precompile_gas: u64,
+    /// Journal of state changes feeding the `POSTSTATE` diff, only fed when
+    /// `Config::poststate` is enabled.
+    poststate_journal: Vec<StateChange>,
+    /// Journal length at entry of every active frame, so a reverting frame
+    /// can truncate away its changes.
+    poststate_checkpoints: Vec<usize>,
+    /// Scope id appended to every event when
+    /// `Config::transaction_scope_ids` is enabled, set by
+    /// `begin_apply_trx` from the transaction hash.
+    scope_id: Option<String>,
+    /// Sender balance before and after the transaction, appended to
+    /// `END_APPLY_TRX` when recorded.
+    sender_balance: Option<(eth::U256, eth::U256)>,
+    /// Tip actually credited to the coinbase, appended to `END_APPLY_TRX`
+    /// when recorded.
+    priority_fee_paid: Option<eth::U256>,
+    /// The transaction's declared gas limit, captured by `begin_apply_trx`
+    /// and echoed on `END_APPLY_TRX` next to the used gas together with
+    /// the used-over-declared ratio.
+    gas_limit: Option<u64>,
+    /// The nonce of a creation transaction (`to` absent), kept until the
+    /// recovered sender makes the deployed address derivable. `None` for
+    /// plain calls and once the address is derived.
+    creation_nonce: Option<u64>,
+    /// Whether this transaction is a contract creation, known once
+    /// `begin_apply_trx` ran; its presence gates the `created_contract`
+    /// field on `END_APPLY_TRX`.
+    creation: Option<bool>,
+    /// The derived deployment address of a creation transaction.
+    created_contract: Option<eth::Address>,
+    /// Completed top-level call frames of the Geth-shaped call tree, only
+    /// fed when `Config::call_tracer` is enabled.
+    call_trace_roots: Vec<TraceFrame>,
+    /// Call frames of the tree still being executed, innermost last.
+    call_trace_stack: Vec<TraceFrame>,
+}
+
+impl TransactionTracer {
+    pub(crate) fn new(ctx: Arc<Context>, block: Arc<BlockState>) -> TransactionTracer {
+        TransactionTracer {
+            ctx: ctx,
+            block: block,
+            next_call_index: 0,
+            call_stack: Vec::new(),
+            buffer: Vec::new(),
+            read_slots: HashSet::new(),
+            written_slots: HashSet::new(),
+            precompile_gas: 0,
+            poststate_journal: Vec::new(),
+            poststate_checkpoints: Vec::new(),
+            scope_id: None,
+            sender_balance: None,
+            priority_fee_paid: None,
+            gas_limit: None,
+            creation_nonce: None,
+            creation: None,
+            created_contract: None,
+            call_trace_roots: Vec::new(),
+            call_trace_stack: Vec::new(),
+        }
+    }
+
+    /// The index of the call frame currently being executed, `0` outside of
+    /// any frame.
+    pub fn call_index(&self) -> u64 {
+        self.call_stack.last().map(|&(index, _)| index).unwrap_or(0)
+    }
+
+    /// Marks the beginning of a transaction application. `to` is `None` for
+    /// contract creation transactions, `chain_id` is `None` for legacy
+    /// transactions without EIP-155 replay protection.
+    pub fn begin_apply_trx(
+        &mut self,
+        hash: &eth::H256,
+        to: Option<&eth::Address>,
+        value: &eth::U256,
+        gas_limit: u64,
+        gas_price: &eth::U256,
+        nonce: u64,
+        data: &[u8],
+        chain_id: Option<u64>,
+    ) {
+        if self.ctx.config().transaction_scope_ids {
+            // The first 8 bytes of the hash are unique enough to shard on
+            // and keep the tag short.
+            self.scope_id = Some(format!("{:x}", hash)[..16].to_owned());
+        }
+        self.gas_limit = Some(gas_limit);
+        self.creation = Some(to.is_none());
+        self.creation_nonce = if to.is_none() { Some(nonce) } else { None };
+        let to = to.cloned().unwrap_or_default();
+        self.emit(
+            Event::new("BEGIN_APPLY_TRX")
+                .h256("hash", hash)
+                .address("to", &to)
+                .u256("value", value)
+                .gas("gas_limit", gas_limit)
+                .u256("gas_price", gas_price)
+                .u64("nonce", nonce)
+                .bytes("data", data)
+                .opt_u64("chain_id", chain_id),
+        );
+    }
+
+    /// Records the pre-execution state of one account the transaction is
+    /// about to touch: its balance, nonce, code hash and the values of the
+    /// storage slots that will be accessed. Called once per touched account
+    /// at transaction start; does nothing unless `Config::prestate` is
+    /// enabled.
+    pub fn record_prestate(
+        &mut self,
+        address: &eth::Address,
+        balance: &eth::U256,
+        nonce: u64,
+        code_hash: &eth::H256,
+        storage: &[(eth::H256, eth::H256)],
+    ) {
+        if !self.ctx.config().prestate {
+            return;
+        }
+        let mut event = Event::new("PRESTATE")
+            .address("address", address)
+            .u256("balance", balance)
+            .u64("nonce", nonce)
+            .h256("code_hash", code_hash);
+        for &(ref key, ref value) in storage {
+            event = event.h256("key", key).h256("value", value);
+        }
+        self.emit(event);
+    }
+
+    /// Records the storage keys declared in the transaction's access list
+    /// (EIP-2930, type-1 and type-2 transactions) as warmed slots, one
+    /// `SLOT_WARMED` line per key. Declared slots start warm, so every
+    /// later SLOAD of them is charged the warm cost; emitting the warm
+    /// baseline up front lets consumers verify the cold/warm attribution
+    /// of the whole transaction. Called once at transaction start, before
+    /// any execution event.
+    pub fn record_access_list(&mut self, access_list: &[(eth::Address, Vec<eth::H256>)]) {
+        for &(ref address, ref keys) in access_list {
+            for key in keys {
+                self.emit(
+                    Event::new("SLOT_WARMED")
+                        .address("address", address)
+                        .h256("key", key),
+                );
+            }
+        }
+    }
+
+    /// Cross-checks the caller-provided transaction `hash` against the
+    /// keccak of the `raw` signed transaction bytes, reporting a
+    /// `TRX_HASH_MISMATCH` on the `DMDEBUG` channel when they differ. The
+    /// hash carried by `BEGIN_APPLY_TRX` is whatever the caller computed;
+    /// this catches integration bugs where it does not belong to the raw
+    /// payload actually applied.
+    #[track_caller]
+    pub fn verify_trx_hash(&mut self, hash: &eth::H256, raw: &[u8]) {
+        let computed = keccak(raw);
+        if computed != *hash {
+            self.emit_debug(
+                Event::debug("TRX_HASH_MISMATCH")
+                    .h256("provided", hash)
+                    .h256("computed", &computed),
+            );
+        }
+    }
+
+    /// Marks the end of the transaction's validation phase — signature and
+    /// nonce checks, balance check, intrinsic gas deduction — with the
+    /// intrinsic gas actually charged. Everything after this marker and
+    /// before `END_APPLY_TRX` is execution, so consumers can bound the
+    /// execution phase without inferring it from the first `EVM_RUN_CALL`.
+    pub fn record_validation_complete(&mut self, intrinsic_gas_charged: u64) {
+        self.emit(
+            Event::new("VALIDATION_COMPLETE").gas("intrinsic_gas_charged", intrinsic_gas_charged),
+        );
+    }
+
+    /// Records one blob versioned hash committed in a type-3 (EIP-4844)
+    /// transaction, keyed by its `index` in the transaction's blob list.
+    /// Never called for non-blob transactions.
+    pub fn record_blob_hash(&mut self, index: u64, versioned_hash: &eth::H256) {
+        self.emit(
+            Event::new("BLOB_HASH")
+                .u64("index", index)
+                .h256("versioned_hash", versioned_hash),
+        );
+    }
+
+    /// Records the recovered sender of the transaction, once known. For a
+    /// creation transaction the sender completes the `sender`/`nonce`
+    /// derivation of the deployed address, reported by `END_APPLY_TRX` as
+    /// `created_contract`.
+    pub fn record_from(&mut self, from: &eth::Address) {
+        if let Some(nonce) = self.creation_nonce.take() {
+            self.created_contract = Some(create_address(from, nonce));
+        }
+        self.emit(Event::new("TRX_FROM").address("from", from));
+    }
+
+    /// Records the application of the gas refund cap at the end of
+    /// execution: the refund counter is capped to `gas_used / divisor`,
+    /// where `divisor` comes from the active fork rules (2 before London,
+    /// 5 from London on, EIP-3529). Carrying the divisor keeps the stream
+    /// self-describing across forks instead of consumers hardcoding it. No
+    /// fork schedule yields a zero divisor; one is an integration bug,
+    /// reported as a `REFUND_CAP_ZERO_DIVISOR` warning on the `DMDEBUG`
+    /// channel instead of dividing by it.
+    #[track_caller]
+    pub fn record_refund_cap(&mut self, gas_used: u64, refund_counter: u64, divisor: u64) {
+        if divisor == 0 {
+            self.emit_debug(
+                Event::debug("REFUND_CAP_ZERO_DIVISOR")
+                    .gas("gas_used", gas_used)
+                    .gas("refund_counter", refund_counter),
+            );
+            return;
+        }
+        let applied = ::std::cmp::min(refund_counter, gas_used / divisor);
+        self.emit(
+            Event::new("REFUND_CAP")
+                .gas("gas_used", gas_used)
+                .gas("refund_counter", refund_counter)
+                .u64("divisor", divisor)
+                .gas("applied", applied),
+        );
+    }
+
+    /// Records how the transaction's effective gas price relates to the
+    /// block's base fee: the margin left above the base fee identifies
+    /// transactions that barely made it into the block. `priority_fee` is
+    /// the tip actually credited to the block author per gas.
+    pub fn record_fee_cap_analysis(
+        &mut self,
+        base_fee: &eth::U256,
+        gas_price: &eth::U256,
+        priority_fee: &eth::U256,
+    ) {
+        let margin = gas_price.saturating_sub(*base_fee);
+        self.emit(
+            Event::new("FEE_CAP_ANALYSIS")
+                .u256("base_fee", base_fee)
+                .u256("gas_price", gas_price)
+                .u256("priority_fee", priority_fee)
+                .u256("margin", &margin),
+        );
+    }
+
+    /// Records the change of one account's storage root over the
+    /// transaction, called at transaction end for each account whose
+    /// storage changed. The roots are read off the state trie after the
+    /// transaction settled, so consumers validating their own trie
+    /// reconstruction can compare against the node's, per account rather
+    /// than only through the block's state root.
+    pub fn record_storage_root_change(
+        &mut self,
+        address: &eth::Address,
+        old_root: &eth::H256,
+        new_root: &eth::H256,
+    ) {
+        self.emit(
+            Event::new("STORAGE_ROOT_CHANGE")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .h256("old_root", old_root)
+                .h256("new_root", new_root),
+        );
+    }
+
+    /// Records the sender's balance as it stood before the transaction and
+    /// after all of it settled (gas buy, value transfer, refund). Appended
+    /// to `END_APPLY_TRX` so accounting consumers read the net effect off
+    /// one line instead of folding the sender's `BALANCE_CHANGE` events.
+    pub fn record_sender_balance(&mut self, before: &eth::U256, after: &eth::U256) {
+        self.sender_balance = Some((*before, *after));
+    }
+
+    /// Records the tip the coinbase received for this transaction under
+    /// EIP-1559: `min(max_priority_fee, max_fee - base_fee) * gas_used`,
+    /// derived here from the fee parameters so consumers read the exact
+    /// amount off `END_APPLY_TRX` instead of recomputing it. For legacy
+    /// transactions pass the gas price as both fee caps.
+    pub fn record_priority_fee(
+        &mut self,
+        max_priority_fee: &eth::U256,
+        max_fee: &eth::U256,
+        base_fee: &eth::U256,
+        gas_used: u64,
+    ) {
+        let per_gas = ::std::cmp::min(*max_priority_fee, max_fee.saturating_sub(*base_fee));
+        self.priority_fee_paid = Some(per_gas * eth::U256::from(gas_used));
+    }
+
+    /// Marks the end of the transaction application, with the total
+    /// `gas_used` by the transaction. `gas_floor` is the EIP-7623 calldata
+    /// gas floor when it bound the transaction's gas (i.e. exceeded the
+    /// execution gas), absent otherwise.
+    pub fn end_apply_trx(&mut self, gas_used: u64, gas_floor: Option<u64>) {
+        *self.block.cumulative_gas_used.lock() += gas_used;
+        self.emit_storage_stats();
+        self.emit_access_sets();
+        if self.precompile_gas > 0 {
+            let event = Event::new("PRECOMPILE_GAS_TOTAL").gas("total", self.precompile_gas);
+            self.emit(event);
+        }
+        self.emit_poststate();
+        let call_traces = self.take_call_traces();
+        let mut event = Event::new("END_APPLY_TRX").gas("gas_used", gas_used);
+        if let Some(gas_floor) = gas_floor {
+            event = event.gas("gas_floor", gas_floor);
+        }
+        // Declared gas limit, co-located with the used gas so bundle
+        // analysis reads both off one line, and the used-over-declared
+        // ratio in basis points: a low ratio marks an over-estimated or
+        // padded transaction.
+        if let Some(gas_limit) = self.gas_limit.take() {
+            event = event.gas("gas_limit", gas_limit);
+            if gas_limit > 0 {
+                event = event.u64("gas_limit_used_bps", gas_used * 10_000 / gas_limit);
+            }
+        }
+        if let Some((before, after)) = self.sender_balance.take() {
+            event = event
+                .u256("sender_balance_before", &before)
+                .u256("sender_balance_after", &after);
+        }
+        if let Some(paid) = self.priority_fee_paid.take() {
+            event = event.u256("priority_fee_paid", &paid);
+        }
+        // Creation transactions carry their derived deployment address so
+        // consumers need not redo the RLP derivation; calls carry the
+        // absent sentinel.
+        if self.creation.take().is_some() {
+            event = match self.created_contract.take() {
+                Some(created) => event.address("created_contract", &created),
+                None => event.field("created_contract", FieldValue::Null),
+            };
+        }
+        // With call index sorting on, the whole transaction is still
+        // buffered at this point, so the raw trace lines must wait for the
+        // flush; printing them here would put them ahead of
+        // `BEGIN_APPLY_TRX` on the stream.
+        if self.ctx.config().sort_by_call_index {
+            self.emit(event);
+            self.flush_sorted(call_traces);
+        } else {
+            for line in call_traces {
+                self.ctx.print_raw(&line);
+            }
+            self.emit(event);
+        }
+    }
+
+    /// Summarises the transaction's storage footprint — unique slots read,
+    /// unique slots written, unique accounts touched — so consumers doing
+    /// state-growth analysis do not have to process every `STORAGE_CHANGE`.
+    /// Silent for transactions that touched no storage.
+    fn emit_storage_stats(&mut self) {
+        if self.read_slots.is_empty() && self.written_slots.is_empty() {
+            return;
+        }
+        let accounts: HashSet<eth::Address> = self
+            .read_slots
+            .iter()
+            .chain(self.written_slots.iter())
+            .map(|&(address, _)| address)
+            .collect();
+        let event = Event::new("STORAGE_STATS")
+            .u64("slots_read", self.read_slots.len() as u64)
+            .u64("slots_written", self.written_slots.len() as u64)
+            .u64("unique_accounts", accounts.len() as u64);
+        self.emit(event);
+    }
+
+    /// Emits the transaction's storage read and write sets as one
+    /// `READ_SET` and one `WRITE_SET` line, each carrying the slot count
+    /// followed by sorted `(address, key)` pairs. Two transactions whose
+    /// write sets do not intersect each other's read or write sets could
+    /// have executed in parallel. Does nothing unless
+    /// `Config::access_sets` is enabled; empty sets are skipped.
+    fn emit_access_sets(&mut self) {
+        if !self.ctx.config().access_sets {
+            return;
+        }
+        let mut events = Vec::new();
+        for &(name, slots) in &[("READ_SET", &self.read_slots), ("WRITE_SET", &self.written_slots)] {
+            if slots.is_empty() {
+                continue;
+            }
+            let sorted: BTreeSet<&(eth::Address, eth::H256)> = slots.iter().collect();
+            let mut event = Event::new(name).u64("count", sorted.len() as u64);
+            for &&(ref address, ref key) in &sorted {
+                event = event.address("address", address).h256("key", key);
+            }
+            events.push(event);
+        }
+        for event in events {
+            self.emit(event);
+        }
+    }
+
+    /// Emits the `POSTSTATE` diff of the finished transaction: one line per
+    /// changed account carrying its final balance (or the absent sentinel
+    /// when only storage changed) and the final value of every changed slot.
+    /// Changes journalled by reverted frames were already discarded by
+    /// `record_revert`, so they never appear here.
+    fn emit_poststate(&mut self) {
+        if !self.ctx.config().poststate || self.poststate_journal.is_empty() {
+            return;
+        }
+        let mut balances: BTreeMap<eth::Address, eth::U256> = BTreeMap::new();
+        let mut storage: BTreeMap<eth::Address, BTreeMap<eth::H256, eth::H256>> = BTreeMap::new();
+        for change in mem::replace(&mut self.poststate_journal, Vec::new()) {
+            match change {
+                StateChange::Balance(address, new) => {
+                    balances.insert(address, new);
+                }
+                StateChange::Storage(address, key, new) => {
+                    storage.entry(address).or_insert_with(BTreeMap::new).insert(key, new);
+                }
+            }
+        }
+        let addresses: BTreeSet<eth::Address> =
+            balances.keys().chain(storage.keys()).cloned().collect();
+        for address in addresses {
+            let mut event = Event::new("POSTSTATE").address("address", &address);
+            event = match balances.get(&address) {
+                Some(balance) => event.u256("balance", balance),
+                None => event.field("balance", FieldValue::Null),
+            };
+            if let Some(slots) = storage.get(&address) {
+                for (key, value) in slots {
+                    event = event.h256("key", key).h256("value", value);
+                }
+            }
+            self.emit(event);
+        }
+    }
+
+    /// Renders the transaction's buffered call tree as one `CALL_TRACE`
+    /// line per top-level frame, in the JSON shape of Geth's `callTracer`.
+    /// The nested object does not fit the flat field model, so like
+    /// `CHAIN_SPEC` it is printed raw rather than through an [`Event`] —
+    /// scope ids and call index sorting do not apply to it. The caller
+    /// prints the returned lines just ahead of `END_APPLY_TRX`, after any
+    /// buffered events, so the trace never precedes the transaction it
+    /// summarises. Empty unless `Config::call_tracer` is enabled; a
+    /// transaction that entered no frame (e.g. a plain transfer) renders
+    /// nothing.
+    fn take_call_traces(&mut self) -> Vec<String> {
+        mem::replace(&mut self.call_trace_roots, Vec::new())
+            .iter()
+            .map(|frame| format!("CALL_TRACE {}", render_call_trace(frame)))
+            .collect()
+    }
+
+    /// Emits a `DMDEBUG` event, tagging it with the `file:line` of the
+    /// `record_*` call site when `Config::debug_source_locations` is
+    /// enabled. The location is the caller's thanks to `#[track_caller]`
+    /// propagation through the annotated `record_*` method.
+    #[track_caller]
+    fn emit_debug(&mut self, event: Event) {
+        let event = if self.ctx.config().debug_source_locations {
+            let location = ::std::panic::Location::caller();
+            event.string("source", &format!("{}:{}", location.file(), location.line()))
+        } else {
+            event
+        };
+        self.emit(event);
+    }
+
+    /// Buffers `event` when call index sorting is enabled, prints it
+    /// immediately otherwise. Appends the transaction scope id when one is
+    /// active.
+    fn emit(&mut self, event: Event) {
+        let event = match self.scope_id {
+            Some(ref id) => event.string("scope", id),
+            None => event,
+        };
+        if self.ctx.config().sort_by_call_index {
+            self.buffer.push(event);
+        } else {
+            self.ctx.emit(event);
+        }
+    }
+
+    /// Emits the buffered events of the finished transaction, stably sorted
+    /// by call index so events of one frame stay in execution order.
+    /// `END_APPLY_TRX` sorts last regardless, closing the group, with the
+    /// raw `CALL_TRACE` lines slotted in directly ahead of it — the same
+    /// position they hold in unsorted mode.
+    fn flush_sorted(&mut self, mut call_traces: Vec<String>) {
+        let mut events = mem::replace(&mut self.buffer, Vec::new());
+        events.sort_by_key(|event| match event.name() {
+            "END_APPLY_TRX" => u64::max_value(),
+            // Whole-transaction summaries carry no call index; defaulting
+            // them to 0 would hoist them ahead of the frames they
+            // summarise, so they pin just before the end marker instead,
+            // keeping their emission order among themselves.
+            "STORAGE_STATS" | "READ_SET" | "WRITE_SET" | "PRECOMPILE_GAS_TOTAL" | "POSTSTATE" => {
+                u64::max_value() - 1
+            }
+            _ => event
+                .fields()
+                .iter()
+                .find(|&&(name, _)| name == "call_index")
+                .and_then(|&(_, ref value)| match *value {
+                    FieldValue::U64(index) => Some(index),
+                    _ => None,
+                })
+                .unwrap_or(0),
+        });
+        for event in events {
+            if event.name() == "END_APPLY_TRX" {
+                for line in call_traces.drain(..) {
+                    self.ctx.print_raw(&line);
+                }
+            }
+            self.ctx.emit(event);
+        }
+        // A buffer missing its end marker still flushes the trace.
+        for line in call_traces {
+            self.ctx.print_raw(&line);
+        }
+    }
+}
+
+impl Tracer for TransactionTracer {
+    fn start_call(
+        &mut self,
+        kind: CallKind,
+        from: &eth::Address,
+        to: &eth::Address,
+        value: &eth::U256,
+        gas_limit: u64,
+        input: &[u8],
+    ) {
+        let reentrant = self.ctx.config().reentrancy_hints
+            && self.call_stack.iter().any(|&(_, ref active)| active == to);
+        self.next_call_index += 1;
+        let call_index = self.next_call_index;
+        self.call_stack.push((call_index, *to));
+        self.poststate_checkpoints.push(self.poststate_journal.len());
+        if self.ctx.config().call_tracer {
+            self.call_trace_stack.push(TraceFrame {
+                kind: kind,
+                from: *from,
+                to: *to,
+                value: *value,
+                gas_limit: gas_limit,
+                gas_used: 0,
+                input: input.to_vec(),
+                output: Vec::new(),
+                calls: Vec::new(),
+            });
+        }
+        self.emit(
+            Event::new("EVM_RUN_CALL")
+                .u64("call_index", call_index)
+                .string("kind", kind.as_str())
+                .address("from", from)
+                .address("to", to)
+                .u256("value", value)
+                .gas("gas_limit", gas_limit)
+                .bytes("input", input),
+        );
+        if reentrant {
+            self.emit(
+                Event::new("REENTRANCY_DETECTED")
+                    .u64("call_index", call_index)
+                    .address("address", to),
+            );
+        }
+    }
+
+    fn end_call(&mut self, gas_left: u64, return_data: &[u8]) {
+        let (call_index, _) = self.call_stack.pop().unwrap_or((0, eth::Address::zero()));
+        self.poststate_checkpoints.pop();
+        if let Some(mut frame) = self.call_trace_stack.pop() {
+            frame.gas_used = frame.gas_limit.saturating_sub(gas_left);
+            frame.output = return_data.to_vec();
+            match self.call_trace_stack.last_mut() {
+                Some(parent) => parent.calls.push(frame),
+                None => self.call_trace_roots.push(frame),
+            }
+        }
+        self.emit(
+            Event::new("EVM_END_CALL")
+                .u64("call_index", call_index)
+                .gas("gas_left", gas_left)
+                .bytes("return_data", return_data),
+        );
+    }
+
+    fn record_revert(&mut self) {
+        if let Some(&checkpoint) = self.poststate_checkpoints.last() {
+            self.poststate_journal.truncate(checkpoint);
+        } else {
+            self.poststate_journal.clear();
+        }
+    }
+
+    fn record_depth_limit_exceeded(&mut self) {
+        self.emit(Event::new("DEPTH_LIMIT_EXCEEDED").u64("call_index", self.call_index()));
+    }
+
+    fn record_balance_change(
+        &mut self,
+        address: &eth::Address,
+        old: &eth::U256,
+        new: &eth::U256,
+        reason: BalanceChangeReason,
+    ) {
+        self.block.touched_addresses.lock().insert(*address);
+        if self.ctx.config().poststate {
+            self.poststate_journal.push(StateChange::Balance(*address, *new));
+        }
+        // Dust filter: a plain transfer moving less than the configured
+        // threshold is tracked above but not emitted.
+        if reason == BalanceChangeReason::Transfer {
+            if let Some(min) = self.ctx.config().min_value_wei {
+                let moved = ::std::cmp::max(old, new).saturating_sub(*::std::cmp::min(old, new));
+                if moved < min {
+                    return;
+                }
+            }
+        }
+        self.emit(
+            Event::new("BALANCE_CHANGE")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .u256("old", old)
+                .u256("new", new)
+                .string("reason", reason.as_str()),
+        );
+    }
+
+    fn record_gas_change(&mut self, old: u64, new: u64, reason: GasChangeReason) {
+        let mut event = Event::new("GAS_CHANGE")
+            .u64("call_index", self.call_index())
+            .gas("old", old)
+            .gas("new", new)
+            .string("reason", reason.as_str());
+        if self.ctx.config().fork_tags {
+            if let Some(fork) = *self.block.active_fork.lock() {
+                event = event.string("fork", fork.as_str());
+            }
+        }
+        self.emit(event);
+    }
+
+    fn record_storage_change(
+        &mut self,
+        address: &eth::Address,
+        key: &eth::H256,
+        old: &eth::H256,
+        new: &eth::H256,
+    ) {
+        self.written_slots.insert((*address, *key));
+        self.block.touched_addresses.lock().insert(*address);
+        if self.ctx.config().poststate {
+            self.poststate_journal.push(StateChange::Storage(*address, *key, *new));
+        }
+        self.emit(
+            Event::new("STORAGE_CHANGE")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .h256("key", key)
+                .h256("old", old)
+                .h256("new", new),
+        );
+    }
+
+    fn record_storage_read(&mut self, address: &eth::Address, key: &eth::H256) {
+        self.read_slots.insert((*address, *key));
+    }
+
+    fn record_sload_gas(&mut self, address: &eth::Address, key: &eth::H256, fork: Fork, cold: bool) {
+        self.read_slots.insert((*address, *key));
+        self.emit(
+            Event::new("SLOAD_GAS")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .h256("key", key)
+                .string("fork", fork.as_str())
+                .bool("cold", cold)
+                .gas("gas_cost", fork.sload_cost(cold)),
+        );
+    }
+
+    fn record_transient_storage_change(
+        &mut self,
+        address: &eth::Address,
+        key: &eth::H256,
+        old: &eth::H256,
+        new: &eth::H256,
+    ) {
+        self.emit(
+            Event::new("TRANSIENT_STORAGE_CHANGE")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .h256("key", key)
+                .h256("old", old)
+                .h256("new", new),
+        );
+    }
+
+    fn record_sstore_gas(
+        &mut self,
+        address: &eth::Address,
+        key: &eth::H256,
+        original: &eth::H256,
+        current: &eth::H256,
+        new: &eth::H256,
+        gas_cost: u64,
+        refund_delta: i64,
+    ) {
+        self.emit(
+            Event::new("SSTORE_GAS")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .h256("key", key)
+                .h256("original", original)
+                .h256("current", current)
+                .h256("new", new)
+                .gas("gas_cost", gas_cost)
+                .i64("refund_delta", refund_delta),
+        );
+    }
+
+    fn record_transient_storage_gas(
+        &mut self,
+        address: &eth::Address,
+        key: &eth::H256,
+        gas_cost: u64,
+        refund_delta: i64,
+    ) {
+        if refund_delta != 0 {
+            self.emit_debug(
+                Event::debug("TRANSIENT_REFUND_VIOLATION")
+                    .u64("call_index", self.call_index())
+                    .address("address", address)
+                    .h256("key", key)
+                    .i64("refund_delta", refund_delta),
+            );
+        }
+        self.emit(
+            Event::new("TSTORE_GAS")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .h256("key", key)
+                .gas("gas_cost", gas_cost),
+        );
+    }
+
+    fn record_contract_deployed(&mut self, address: &eth::Address, code: &[u8], gas_left: u64) {
+        self.block.touched_addresses.lock().insert(*address);
+        let deposit = CODE_DEPOSIT_GAS_PER_BYTE * code.len() as u64;
+        if gas_left < deposit {
+            self.emit(
+                Event::new("CODE_STORAGE_OUT_OF_GAS")
+                    .u64("call_index", self.call_index())
+                    .address("address", address)
+                    .u64("code_length", code.len() as u64)
+                    .gas("deposit", deposit)
+                    .gas("gas_left", gas_left),
+            );
+            return;
+        }
+        self.block
+            .created_contracts
+            .lock()
+            .push((*address, keccak(code)));
+        self.emit(
+            Event::new("CODE_CHANGE")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .bytes("code", code)
+                .gas("deposit", deposit),
+        );
+        self.record_gas_change(gas_left, gas_left - deposit, GasChangeReason::DeploymentCodeStorage);
+    }
+
+    fn record_log(&mut self, address: &eth::Address, topics: &[eth::H256], data: &[u8]) {
+        // The explicit count lets consumers reconstruct the 375-gas-per-topic
+        // LOG cost without mapping back to the LOG0..LOG4 opcode.
+        let mut event = Event::new("ADD_LOG")
+            .u64("call_index", self.call_index())
+            .address("address", address)
+            .u64("topic_count", topics.len() as u64);
+        for topic in topics {
+            event = event.h256("topic", topic);
+        }
+        self.emit(event.bytes("data", data));
+    }
+
+    fn record_selfdestruct(
+        &mut self,
+        address: &eth::Address,
+        beneficiary: &eth::Address,
+        balance: &eth::U256,
+    ) {
+        self.block.touched_addresses.lock().insert(*address);
+        self.block.destroyed_contracts.lock().push(*address);
+        self.emit(
+            Event::new("SUICIDE_CHANGE")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .address("beneficiary", beneficiary)
+                .u256("balance", balance)
+                .bool("self_burn", address == beneficiary),
+        );
+    }
+
+    fn record_selfdestruct_gas(&mut self, gas_left: u64, fork: Fork, cold_beneficiary: bool) {
+        let mut charges = Vec::with_capacity(2);
+        let base = fork.selfdestruct_cost();
+        if base > 0 {
+            charges.push((base, GasChangeReason::SelfDestruct));
+        }
+        if cold_beneficiary && fork >= Fork::Berlin {
+            charges.push((2600, GasChangeReason::ColdBeneficiaryAccess));
+        }
+        let mut gas_left = gas_left;
+        for (charge, reason) in charges {
+            // A frame with less gas than the charge is an integration bug —
+            // the EVM fails such a SELFDESTRUCT before metering it. Report
+            // it off-stream rather than underflowing.
+            if gas_left < charge {
+                self.emit_debug(
+                    Event::debug("SELFDESTRUCT_GAS_UNDERFLOW")
+                        .u64("call_index", self.call_index())
+                        .gas("gas_left", gas_left)
+                        .gas("charge", charge)
+                        .string("reason", reason.as_str()),
+                );
+                return;
+            }
+            self.record_gas_change(gas_left, gas_left - charge, reason);
+            gas_left -= charge;
+        }
+    }
+
+    fn record_precompile_refund(&mut self, address: &eth::Address, refund: u64) {
+        self.emit(
+            Event::new("PRECOMPILE_REFUND")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .gas("refund", refund),
+        );
+    }
+
+    fn record_precompile_gas(&mut self, address: &eth::Address, gas_cost: u64) {
+        self.precompile_gas += gas_cost;
+        self.emit(
+            Event::new("PRECOMPILE_GAS")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .gas("gas_cost", gas_cost),
+        );
+    }
+
+    fn record_hashing_precompile_gas(&mut self, address: &eth::Address, input_length: u64) {
+        let (base, per_word) = match hashing_precompile_schedule(address) {
+            Some(schedule) => schedule,
+            None => return,
+        };
+        let gas_cost = base + per_word * ((input_length + 31) / 32);
+        self.precompile_gas += gas_cost;
+        self.emit(
+            Event::new("HASHING_PRECOMPILE_GAS")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .u64("input_length", input_length)
+                .gas("gas_cost", gas_cost),
+        );
+    }
+
+    fn record_keccak(
+        &mut self,
+        hash: &eth::H256,
+        data: &[u8],
+        hashing_gas: u64,
+        memory_expansion_gas: u64,
+    ) {
+        self.emit(
+            Event::new("EVM_KECCAK")
+                .u64("call_index", self.call_index())
+                .h256("hash", hash)
+                .bytes("data", data)
+                .gas("hashing_gas", hashing_gas)
+                .gas("memory_expansion_gas", memory_expansion_gas),
+        );
+    }
+
+    fn record_balance_read(
+        &mut self,
+        address: &eth::Address,
+        balance: &eth::U256,
+        cold: bool,
+        self_balance: bool,
+    ) {
+        self.emit(
+            Event::new("BALANCE_READ")
+                .u64("call_index", self.call_index())
+                .address("address", address)
+                .u256("balance", balance)
+                .bool("cold", cold)
+                .bool("self_balance", self_balance),
+        );
+    }
+
+    fn record_return_data_copy(&mut self, size: u64, gas_cost: u64) {
+        self.emit(
+            Event::new("RETURN_DATA_COPY")
+                .u64("call_index", self.call_index())
+                .u64("size", size)
+                .gas("gas_cost", gas_cost),
+        );
+    }
+
+    fn record_gas_opcode(&mut self, observed_gas: u64) {
+        if !self.ctx.config().opcode_level {
+            return;
+        }
+        self.emit(
+            Event::new("GAS_OPCODE")
+                .u64("call_index", self.call_index())
+                .gas("observed_gas", observed_gas),
+        );
+    }
+
+    fn record_blobhash_opcode(&mut self, index: u64, versioned_hash: &eth::H256) {
+        if !self.ctx.config().opcode_level {
+            return;
+        }
+        self.emit(
+            Event::new("BLOBHASH")
+                .u64("call_index", self.call_index())
+                .u64("index", index)
+                .h256("versioned_hash", versioned_hash),
+        );
+    }
+
+    fn record_blob_base_fee_read(&mut self, value: &eth::U256) {
+        self.emit(
+            Event::new("ENV_READ")
+                .u64("call_index", self.call_index())
+                .string("name", "blob_base_fee")
+                .u256("value", value),
+        );
+    }
+
+    fn record_exp_gas(&mut self, exponent_bytes: u64, gas_cost: u64) {
+        self.emit(
+            Event::new("EXP_GAS")
+                .u64("call_index", self.call_index())
+                .u64("exponent_bytes", exponent_bytes)
+                .gas("gas_cost", gas_cost),
+        );
+    }
+
+    fn record_mcopy(&mut self, dst_offset: u64, src_offset: u64, size: u64, gas_cost: u64) {
+        self.emit(
+            Event::new("MCOPY")
+                .u64("call_index", self.call_index())
+                .u64("dst_offset", dst_offset)
+                .u64("src_offset", src_offset)
+                .u64("size", size)
+                .gas("gas_cost", gas_cost),
+        );
+    }
+
+    fn record_code_analysis(&mut self, code_size: u64, analysis_ns: u64) {
+        self.emit_debug(
+            Event::debug("CODE_ANALYSIS")
+                .u64("call_index", self.call_index())
+                .u64("code_size", code_size)
+                .u64("analysis_ns", analysis_ns),
+        );
+    }
+
+    fn record_eof_deploy(&mut self, code: &[u8]) {
+        if let Some(layout) = parse_eof_layout(code) {
+            self.emit(
+                Event::new("EOF_DEPLOY")
+                    .u64("call_index", self.call_index())
+                    .u64("version", layout.version)
+                    .u64("code_sections", layout.code_sections)
+                    .u64("data_section_size", layout.data_section_size),
+            );
+        }
+    }
+
+    fn record_create2_preimage(
+        &mut self,
+        sender: &eth::Address,
+        salt: &eth::H256,
+        init_code_hash: &eth::H256,
+        derived_address: &eth::Address,
+    ) {
+        self.emit(
+            Event::new("CREATE2_PREIMAGE")
+                .u64("call_index", self.call_index())
+                .address("sender", sender)
+                .h256("salt", salt)
+                .h256("init_code_hash", init_code_hash)
+                .address("derived_address", derived_address),
+        );
+    }
+}
+
+/// Nonoperative tracer. Does not record anything.
+pub struct NoopTracer;
+
+impl Tracer for NoopTracer {
+    fn start_call(
+        &mut self,
+        _: CallKind,
+        _: &eth::Address,
+        _: &eth::Address,
+        _: &eth::U256,
+        _: u64,
+        _: &[u8],
+    ) {
+    }
+    fn end_call(&mut self, _: u64, _: &[u8]) {}
+    fn record_revert(&mut self) {}
+    fn record_depth_limit_exceeded(&mut self) {}
+    fn record_balance_change(
+        &mut self,
+        _: &eth::Address,
+        _: &eth::U256,
+        _: &eth::U256,
+        _: BalanceChangeReason,
+    ) {
+    }
+    fn record_gas_change(&mut self, _: u64, _: u64, _: GasChangeReason) {}
+    fn record_storage_change(&mut self, _: &eth::Address, _: &eth::H256, _: &eth::H256, _: &eth::H256) {}
+    fn record_storage_read(&mut self, _: &eth::Address, _: &eth::H256) {}
+    fn record_sload_gas(&mut self, _: &eth::Address, _: &eth::H256, _: Fork, _: bool) {}
+    fn record_transient_storage_change(
+        &mut self,
+        _: &eth::Address,
+        _: &eth::H256,
+        _: &eth::H256,
+        _: &eth::H256,
+    ) {
+    }
+    fn record_sstore_gas(
+        &mut self,
+        _: &eth::Address,
+        _: &eth::H256,
+        _: &eth::H256,
+        _: &eth::H256,
+        _: &eth::H256,
+        _: u64,
+        _: i64,
+    ) {
+    }
+    fn record_transient_storage_gas(&mut self, _: &eth::Address, _: &eth::H256, _: u64, _: i64) {}
+    fn record_contract_deployed(&mut self, _: &eth::Address, _: &[u8], _: u64) {}
+    fn record_log(&mut self, _: &eth::Address, _: &[eth::H256], _: &[u8]) {}
+    fn record_selfdestruct(&mut self, _: &eth::Address, _: &eth::Address, _: &eth::U256) {}
+    fn record_selfdestruct_gas(&mut self, _: u64, _: Fork, _: bool) {}
+    fn record_precompile_refund(&mut self, _: &eth::Address, _: u64) {}
+    fn record_precompile_gas(&mut self, _: &eth::Address, _: u64) {}
+    fn record_hashing_precompile_gas(&mut self, _: &eth::Address, _: u64) {}
+    fn record_keccak(&mut self, _: &eth::H256, _: &[u8], _: u64, _: u64) {}
+    fn record_balance_read(&mut self, _: &eth::Address, _: &eth::U256, _: bool, _: bool) {}
+    fn record_return_data_copy(&mut self, _: u64, _: u64) {}
+    fn record_gas_opcode(&mut self, _: u64) {}
+    fn record_blobhash_opcode(&mut self, _: u64, _: &eth::H256) {}
+    fn record_blob_base_fee_read(&mut self, _: &eth::U256) {}
+    fn record_exp_gas(&mut self, _: u64, _: u64) {}
+    fn record_mcopy(&mut self, _: u64, _: u64, _: u64, _: u64) {}
+    fn record_code_analysis(&mut self, _: u64, _: u64) {}
+    fn record_eof_deploy(&mut self, _: &[u8]) {}
+    fn record_create2_preimage(
+        &mut self,
+        _: &eth::Address,
+        _: &eth::H256,
+        _: &eth::H256,
+        _: &eth::Address,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::Config;
+    use eth::{H256, U256};
+    use printer::MemoryPrinter;
+
+    fn test_tracer() -> (TransactionTracer, Arc<MemoryPrinter>) {
+        let printer = Arc::new(MemoryPrinter::new());
+        let ctx = Context::new(Config::default(), printer.clone());
+        (ctx.block_context().transaction_tracer(), printer)
+    }
+
+    fn begin_trx(tracer: &mut TransactionTracer, chain_id: Option<u64>) {
+        tracer.begin_apply_trx(
+            &H256::from_low_u64_be(1),
+            None,
+            &U256::zero(),
+            21000,
+            &U256::from(1_000_000_000u64),
+            0,
+            &[],
+            chain_id,
+        );
+    }
+
+    #[test]
+    fn begin_apply_trx_carries_chain_id() {
+        let (mut tracer, printer) = test_tracer();
+        begin_trx(&mut tracer, Some(1));
+
+        let lines = printer.lines();
+        let fields: Vec<&str> = lines[0].split(' ').collect();
+        assert_eq!(fields[1], "BEGIN_APPLY_TRX");
+        assert_eq!(*fields.last().unwrap(), "1");
+    }
+
+    #[test]
+    fn code_analysis_goes_to_the_debug_channel() {
+        let (mut tracer, printer) = test_tracer();
+        tracer.record_code_analysis(24576, 1800);
+
+        assert!(printer.lines_on(::printer::Channel::Log).is_empty());
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Debug),
+            vec!["CODE_ANALYSIS 0 24576 1800".to_owned()]
+        );
+    }
+
+    #[test]
+    fn gas_as_string_quotes_gas_values_in_json_mode() {
+        use config::Format;
+
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            format: Format::Json,
+            gas_as_string: true,
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let mut tracer = ctx.block_context().transaction_tracer();
+        tracer.end_apply_trx(21000, None);
+
+        assert_eq!(
+            printer.lines(),
+            vec!["DMLOG {\"type\":\"end_apply_trx\",\"gas_used\":\"21000\"}".to_owned()]
+        );
+    }
+
+    #[test]
+    fn reentrant_call_is_flagged_when_hints_are_enabled() {
+        use eth::Address;
+
+        let a = Address::from_low_u64_be(0xaa);
+        let b = Address::from_low_u64_be(0xbb);
+        let c = Address::from_low_u64_be(0xcc);
+        let call = |tracer: &mut TransactionTracer, from: &Address, to: &Address| {
+            tracer.start_call(CallKind::Call, from, to, &U256::zero(), 100_000, &[]);
+        };
+
+        for &(hints, expected) in &[(true, 1usize), (false, 0)] {
+            let printer = Arc::new(MemoryPrinter::new());
+            let config = Config {
+                reentrancy_hints: hints,
+                ..Default::default()
+            };
+            let ctx = Context::new(config, printer.clone());
+            let mut tracer = ctx.block_context().transaction_tracer();
+
+            // A -> B -> C -> B re-enters B while B is still on the stack.
+            call(&mut tracer, &a, &b);
+            call(&mut tracer, &b, &c);
+            call(&mut tracer, &c, &b);
+
+            let hints_emitted: Vec<String> = printer
+                .lines()
+                .into_iter()
+                .filter(|line| line.starts_with("DMLOG REENTRANCY_DETECTED "))
+                .collect();
+            assert_eq!(hints_emitted.len(), expected);
+            if hints {
+                assert_eq!(hints_emitted[0], format!("DMLOG REENTRANCY_DETECTED 3 {:x}", b));
+            }
+        }
+    }
+
+    #[test]
+    fn add_log_topic_count_reconstructs_per_topic_gas() {
+        use eth::Address;
+
+        const GAS_PER_TOPIC: u64 = 375;
+
+        let (mut tracer, printer) = test_tracer();
+        let address = Address::from_low_u64_be(0xc0de);
+        let topics: Vec<H256> = (1..=4).map(H256::from_low_u64_be).collect();
+        tracer.record_log(&address, &[], &[0x01]); // LOG0
+        tracer.record_log(&address, &topics, &[0x01]); // LOG4
+
+        let lines = printer.lines();
+        let count = |line: &str| line.split(' ').nth(4).unwrap().parse::<u64>().unwrap();
+        assert_eq!(count(&lines[0]), 0);
+        assert_eq!(count(&lines[1]), 4);
+        assert_eq!(
+            (count(&lines[1]) - count(&lines[0])) * GAS_PER_TOPIC,
+            1500
+        );
+    }
+
+    #[test]
+    fn create2_preimage_matches_the_eip1014_vector() {
+        use eth::Address;
+
+        // First example of EIP-1014: zero sender, zero salt, empty init
+        // code (whose keccak is the well-known empty hash).
+        let sender = Address::zero();
+        let salt = H256::zero();
+        let init_code_hash: H256 =
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+                .parse()
+                .unwrap();
+        let derived: Address = "4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38".parse().unwrap();
+
+        let (mut tracer, printer) = test_tracer();
+        tracer.record_create2_preimage(&sender, &salt, &init_code_hash, &derived);
+
+        assert_eq!(
+            printer.lines(),
+            vec![format!(
+                "DMLOG CREATE2_PREIMAGE 0 . . {:x} {:x}",
+                init_code_hash, derived
+            )]
+        );
+    }
+
+    #[test]
+    fn scope_ids_tag_every_event_of_a_transaction() {
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            transaction_scope_ids: true,
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let block = ctx.block_context();
+
+        for num in 1..3u64 {
+            let mut tracer = block.transaction_tracer();
+            begin_trx(&mut tracer, Some(num));
+            tracer.record_gas_change(100_000, 79_000, GasChangeReason::IntrinsicGas);
+            tracer.end_apply_trx(21_000, None);
+        }
+
+        let lines = printer.lines();
+        let expected = format!("{:x}", H256::from_low_u64_be(1))[..16].to_owned();
+        assert_eq!(lines.len(), 6);
+        for line in &lines {
+            assert!(line.ends_with(&expected), "missing scope tag: {}", line);
+        }
+    }
+
+    #[test]
+    fn access_sets_expose_non_conflicting_transactions() {
+        use eth::Address;
+
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            access_sets: true,
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let block = ctx.block_context();
+        let contract = Address::from_low_u64_be(0xc0de);
+        let (k1, k2) = (H256::from_low_u64_be(1), H256::from_low_u64_be(2));
+
+        // Two transactions touching disjoint slots of the same contract.
+        let mut first = block.transaction_tracer();
+        first.record_storage_read(&contract, &k1);
+        first.record_storage_change(&contract, &k1, &H256::zero(), &k2);
+        first.end_apply_trx(30_000, None);
+
+        let mut second = block.transaction_tracer();
+        second.record_storage_change(&contract, &k2, &H256::zero(), &k1);
+        second.end_apply_trx(30_000, None);
+
+        let sets: Vec<String> = printer
+            .lines()
+            .into_iter()
+            .filter(|line| line.contains("_SET "))
+            .collect();
+        assert_eq!(
+            sets,
+            vec![
+                format!("DMLOG READ_SET 1 {:x} {:x}", contract, k1),
+                format!("DMLOG WRITE_SET 1 {:x} {:x}", contract, k1),
+                format!("DMLOG WRITE_SET 1 {:x} {:x}", contract, k2),
+            ]
+        );
+        // The second transaction's write set misses the first's read and
+        // write sets entirely: they could have run in parallel.
+    }
+
+    #[test]
+    fn poststate_diff_excludes_reverted_changes() {
+        use eth::Address;
+
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            poststate: true,
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let mut tracer = ctx.block_context().transaction_tracer();
+        let a = Address::from_low_u64_be(0xaa);
+        let b = Address::from_low_u64_be(0xbb);
+        let key = H256::from_low_u64_be(1);
+
+        tracer.start_call(CallKind::Call, &b, &a, &U256::zero(), 100_000, &[]);
+        tracer.record_balance_change(&a, &U256::zero(), &U256::from(10), BalanceChangeReason::Transfer);
+        tracer.record_storage_change(&a, &key, &H256::zero(), &H256::from_low_u64_be(2));
+        // An inner call touches B, then reverts: nothing of it survives.
+        tracer.start_call(CallKind::Call, &a, &b, &U256::zero(), 50_000, &[]);
+        tracer.record_balance_change(&b, &U256::zero(), &U256::from(99), BalanceChangeReason::Transfer);
+        tracer.record_storage_change(&b, &key, &H256::zero(), &H256::from_low_u64_be(3));
+        tracer.record_revert();
+        tracer.end_call(40_000, &[]);
+        // The outer frame overwrites its own earlier slot value.
+        tracer.record_storage_change(&a, &key, &H256::from_low_u64_be(2), &H256::from_low_u64_be(4));
+        tracer.end_call(90_000, &[]);
+        tracer.end_apply_trx(60_000, None);
+
+        let poststate: Vec<String> = printer
+            .lines()
+            .into_iter()
+            .filter(|line| line.starts_with("DMLOG POSTSTATE "))
+            .collect();
+        assert_eq!(
+            poststate,
+            vec![format!(
+                "DMLOG POSTSTATE {:x} a {:x} {:x}",
+                a,
+                key,
+                H256::from_low_u64_be(4)
+            )]
+        );
+    }
+
+    #[test]
+    fn prestate_is_gated_and_carries_storage_pairs() {
+        use eth::Address;
+
+        let address = Address::from_low_u64_be(0xc0de);
+        let balance = U256::from(1_000_000u64);
+        let code_hash = H256::from_low_u64_be(0xc0);
+        let storage = [(H256::from_low_u64_be(1), H256::from_low_u64_be(2))];
+
+        for &(enabled, expected_lines) in &[(true, 1usize), (false, 0)] {
+            let printer = Arc::new(MemoryPrinter::new());
+            let config = Config {
+                prestate: enabled,
+                ..Default::default()
+            };
+            let ctx = Context::new(config, printer.clone());
+            let mut tracer = ctx.block_context().transaction_tracer();
+            tracer.record_prestate(&address, &balance, 7, &code_hash, &storage);
+
+            let lines = printer.lines();
+            assert_eq!(lines.len(), expected_lines);
+            if enabled {
+                assert_eq!(
+                    lines[0],
+                    format!(
+                        "DMLOG PRESTATE {:x} {:x} 7 {:x} {:x} {:x}",
+                        address, balance, code_hash, storage[0].0, storage[0].1
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn precompile_gas_total_sums_over_the_transaction() {
+        use eth::Address;
+
+        let (mut tracer, printer) = test_tracer();
+        // ecrecover, sha256 and identity, as a signature-checking contract
+        // would call them.
+        tracer.record_precompile_gas(&Address::from_low_u64_be(0x01), 3000);
+        tracer.record_precompile_gas(&Address::from_low_u64_be(0x02), 72);
+        tracer.record_precompile_gas(&Address::from_low_u64_be(0x04), 18);
+        tracer.end_apply_trx(50_000, None);
+
+        let lines = printer.lines();
+        assert_eq!(lines[lines.len() - 2], "DMLOG PRECOMPILE_GAS_TOTAL 3090");
+
+        // No precompile calls, no summary.
+        let (mut plain, plain_printer) = test_tracer();
+        plain.end_apply_trx(21_000, None);
+        assert_eq!(plain_printer.lines().len(), 1);
+    }
+
+    #[test]
+    fn depth_limit_exceeded_is_attributed_to_the_calling_frame() {
+        use eth::Address;
+
+        let (mut tracer, printer) = test_tracer();
+        let contract = Address::from_low_u64_be(0xc0de);
+
+        // A contract recursing into itself until frame 1024, where the next
+        // call fails without entering a frame.
+        for _ in 0..1024 {
+            tracer.start_call(CallKind::Call, &contract, &contract, &U256::zero(), 100_000, &[]);
+        }
+        tracer.record_depth_limit_exceeded();
+
+        assert_eq!(
+            printer.lines().last().unwrap(),
+            "DMLOG DEPTH_LIMIT_EXCEEDED 1024"
+        );
+    }
+
+    #[test]
+    fn trx_hash_mismatch_is_reported_on_the_debug_channel() {
+        // keccak of the empty byte string, the one hash everyone knows.
+        let empty_hash: H256 =
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+                .parse()
+                .unwrap();
+
+        let (mut tracer, printer) = test_tracer();
+        tracer.verify_trx_hash(&empty_hash, &[]);
+        assert!(printer.lines().is_empty());
+
+        let wrong = H256::from_low_u64_be(1);
+        tracer.verify_trx_hash(&wrong, &[]);
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Debug),
+            vec![format!("TRX_HASH_MISMATCH {:x} {:x}", wrong, empty_hash)]
+        );
+    }
+
+    #[test]
+    fn transient_storage_stays_apart_from_persistent_storage() {
+        use eth::Address;
+
+        let (mut tracer, printer) = test_tracer();
+        let address = Address::from_low_u64_be(0xc0de);
+        let key = H256::from_low_u64_be(1);
+        let value = H256::from_low_u64_be(2);
+
+        // A contract mixing TSTORE and SSTORE on the same slot number: the
+        // transient write costs a flat 100 gas, the persistent one goes
+        // through the usual SSTORE metering.
+        tracer.record_transient_storage_change(&address, &key, &H256::zero(), &value);
+        tracer.record_gas_change(100_000, 99_900, GasChangeReason::TransientStorageStore);
+        tracer.record_storage_change(&address, &key, &H256::zero(), &value);
+        tracer.record_sstore_gas(&address, &key, &H256::zero(), &H256::zero(), &value, 20000, 0);
+        tracer.end_apply_trx(60_000, None);
+
+        let lines = printer.lines();
+        assert!(lines[0].starts_with("DMLOG TRANSIENT_STORAGE_CHANGE "));
+        assert!(lines[1].ends_with("transient_storage_store"));
+        assert!(lines[2].starts_with("DMLOG STORAGE_CHANGE "));
+        // Transient slots are discarded with the transaction: only the
+        // persistent write counts towards the storage statistics.
+        assert_eq!(lines[lines.len() - 2], "DMLOG STORAGE_STATS 0 1 1");
+    }
+
+    #[test]
+    fn transient_storage_never_grants_a_refund() {
+        use eth::Address;
+
+        let (mut tracer, printer) = test_tracer();
+        let address = Address::from_low_u64_be(0xc0de);
+        let key = H256::from_low_u64_be(1);
+
+        // A well-behaved TSTORE: flat 100 gas, no refund anywhere on the
+        // protocol stream.
+        tracer.record_transient_storage_gas(&address, &key, 100, 0);
+        assert!(printer.lines_on(::printer::Channel::Debug).is_empty());
+        assert_eq!(
+            printer.lines(),
+            vec![format!("DMLOG TSTORE_GAS 0 {:x} {:x} 100", address, key)]
+        );
+        assert!(!printer.lines().iter().any(|line| line.contains("refund")));
+
+        // An integration computing a refund for transient storage violates
+        // EIP-1153; the invariant check reports it off-stream.
+        tracer.record_transient_storage_gas(&address, &key, 100, -15000);
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Debug),
+            vec![format!(
+                "TRANSIENT_REFUND_VIOLATION 0 {:x} {:x} -15000",
+                address, key
+            )]
+        );
+    }
+
+    #[test]
+    fn storage_stats_count_unique_slots_and_accounts() {
+        use eth::Address;
+
+        let (mut tracer, printer) = test_tracer();
+        let a = Address::from_low_u64_be(0xaa);
+        let b = Address::from_low_u64_be(0xbb);
+        let k1 = H256::from_low_u64_be(1);
+        let k2 = H256::from_low_u64_be(2);
+
+        // Two reads of the same slot count once; a slot both read and
+        // written counts in both tallies; accounts are deduplicated.
+        tracer.record_storage_read(&a, &k1);
+        tracer.record_storage_read(&a, &k1);
+        tracer.record_storage_read(&b, &k1);
+        tracer.record_storage_change(&a, &k1, &H256::zero(), &k2);
+        tracer.record_storage_change(&a, &k2, &H256::zero(), &k1);
+        tracer.end_apply_trx(60_000, None);
+
+        let lines = printer.lines();
+        assert_eq!(lines[lines.len() - 2], "DMLOG STORAGE_STATS 2 2 2");
+
+        // A plain transfer stays silent.
+        let (mut plain, plain_printer) = test_tracer();
+        plain.end_apply_trx(21_000, None);
+        assert_eq!(plain_printer.lines(), vec!["DMLOG END_APPLY_TRX 21000".to_owned()]);
+    }
+
+    #[test]
+    fn sender_balance_pair_lands_on_end_apply_trx() {
+        let (mut tracer, printer) = test_tracer();
+        // 1 ETH sender transferring 0.1 ETH and paying 21000 gas at
+        // 50 gwei: the pair carries the net effect of both.
+        let before = U256::from(1_000_000_000_000_000_000u64);
+        let after = before
+            - U256::from(100_000_000_000_000_000u64)
+            - U256::from(21_000u64) * U256::from(50_000_000_000u64);
+        tracer.record_sender_balance(&before, &after);
+        tracer.end_apply_trx(21_000, None);
+
+        assert_eq!(
+            printer.lines(),
+            vec![format!("DMLOG END_APPLY_TRX 21000 {:x} {:x}", before, after)]
+        );
+    }
+
+    #[test]
+    fn call_variants_attribute_their_base_cost_to_the_right_reason() {
+        use eth::Address;
+
+        let variants = [
+            (CallKind::Call, "call"),
+            (CallKind::CallCode, "call_code"),
+            (CallKind::DelegateCall, "delegate_call"),
+            (CallKind::StaticCall, "static_call"),
+        ];
+
+        let (mut tracer, printer) = test_tracer();
+        let mut gas = 1_000_000u64;
+        for &(kind, _) in &variants {
+            tracer.start_call(
+                kind,
+                &Address::from_low_u64_be(0xaa),
+                &Address::from_low_u64_be(0xbb),
+                &U256::zero(),
+                gas,
+                &[],
+            );
+            // 700 gas base for every call variant since EIP-150; the
+            // variants differ in stipend and value handling, not here.
+            tracer.record_gas_change(gas, gas - 700, kind.base_gas_reason().unwrap());
+            gas -= 700;
+        }
+        assert!(CallKind::Create.base_gas_reason().is_none());
+        assert!(CallKind::Create2.base_gas_reason().is_none());
+
+        let reasons: Vec<String> = printer
+            .lines()
+            .into_iter()
+            .filter(|line| line.starts_with("DMLOG GAS_CHANGE "))
+            .map(|line| line.split(' ').last().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            reasons,
+            variants.iter().map(|&(_, reason)| reason.to_owned()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn refund_cap_carries_the_fork_divisor() {
+        let (mut tracer, printer) = test_tracer();
+        // Same execution, both sides of London: a 100k-gas transaction with
+        // a 60k refund counter is capped at half pre-London (EIP-3529 then
+        // tightened the cap to a fifth).
+        tracer.record_refund_cap(100_000, 60_000, 2);
+        tracer.record_refund_cap(100_000, 60_000, 5);
+
+        assert_eq!(
+            printer.lines(),
+            vec![
+                "DMLOG REFUND_CAP 100000 60000 2 50000".to_owned(),
+                "DMLOG REFUND_CAP 100000 60000 5 20000".to_owned(),
+            ]
+        );
+
+        // An embedder passing a zero divisor has its fork wiring wrong;
+        // the invariant check reports it off-stream instead of panicking.
+        let (mut tracer, printer) = test_tracer();
+        tracer.record_refund_cap(100_000, 60_000, 0);
+        assert!(printer.lines_on(::printer::Channel::Log).is_empty());
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Debug),
+            vec!["REFUND_CAP_ZERO_DIVISOR 100000 60000".to_owned()]
+        );
+    }
+
+    #[test]
+    fn keccak_splits_hashing_gas_from_memory_expansion() {
+        use keccak_hash::keccak;
+        use rustc_hex::ToHex;
+
+        let (mut tracer, printer) = test_tracer();
+        // Hashing 64 bytes starting beyond the current memory size: 30 base
+        // plus 6 * 2 words of hashing gas, plus the expansion charged for
+        // the newly covered region.
+        let data = [0x11u8; 64];
+        tracer.record_keccak(&keccak(&data[..]), &data, 42, 9);
+
+        assert_eq!(
+            printer.lines(),
+            vec![format!(
+                "DMLOG EVM_KECCAK 0 {:x} {} 42 9",
+                keccak(&data[..]),
+                data.to_hex()
+            )]
+        );
+    }
+
+    #[test]
+    fn exp_gas_scales_with_exponent_length() {
+        let (mut tracer, printer) = test_tracer();
+        // 10 + 50 per exponent byte: a one-byte, a four-byte and a full
+        // 256-bit exponent.
+        for &bytes in &[1u64, 4, 32] {
+            tracer.record_exp_gas(bytes, 10 + 50 * bytes);
+        }
+
+        assert_eq!(
+            printer.lines(),
+            vec![
+                "DMLOG EXP_GAS 0 1 60".to_owned(),
+                "DMLOG EXP_GAS 0 4 210".to_owned(),
+                "DMLOG EXP_GAS 0 32 1610".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn mcopy_carries_offsets_size_and_gas() {
+        let (mut tracer, printer) = test_tracer();
+        // Copying 96 bytes (3 words) with no expansion: 3 + 3 * 3 = 12 gas,
+        // as a contract shifting a 3-word buffer in place would be charged.
+        tracer.record_mcopy(0x80, 0x20, 96, 12);
+
+        assert_eq!(printer.lines(), vec!["DMLOG MCOPY 0 128 32 96 12".to_owned()]);
+    }
+
+    #[test]
+    fn sort_by_call_index_groups_events_per_frame() {
+        use eth::Address;
+
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            sort_by_call_index: true,
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let mut tracer = ctx.block_context().transaction_tracer();
+        let a = Address::from_low_u64_be(0xaa);
+        let b = Address::from_low_u64_be(0xbb);
+
+        begin_trx(&mut tracer, Some(1));
+        tracer.start_call(CallKind::Call, &a, &b, &U256::zero(), 100_000, &[]);
+        tracer.record_gas_change(100_000, 99_300, GasChangeReason::Call);
+        tracer.record_storage_read(&b, &H256::from_low_u64_be(7));
+        tracer.record_precompile_gas(&Address::from_low_u64_be(2), 3000);
+        tracer.start_call(CallKind::Call, &b, &a, &U256::zero(), 50_000, &[]);
+        tracer.end_call(40_000, &[]);
+        // Back in frame 1: interleaved with frame 2 in execution order,
+        // grouped with the frame's earlier events once sorted.
+        tracer.record_gas_change(99_300, 98_000, GasChangeReason::CallLeftOver);
+        tracer.end_call(90_000, &[]);
+
+        // Nothing leaves the tracer until the transaction ends.
+        assert!(printer.lines().is_empty());
+        tracer.end_apply_trx(21000, None);
+
+        let names: Vec<String> = printer
+            .lines()
+            .iter()
+            .map(|line| {
+                let fields: Vec<&str> = line.split(' ').collect();
+                format!("{} {}", fields[1], fields[2])
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                format!("BEGIN_APPLY_TRX {:x}", H256::from_low_u64_be(1)),
+                "EVM_RUN_CALL 1".to_owned(),
+                "GAS_CHANGE 1".to_owned(),
+                "PRECOMPILE_GAS 1".to_owned(),
+                "GAS_CHANGE 1".to_owned(),
+                "EVM_END_CALL 1".to_owned(),
+                "EVM_RUN_CALL 2".to_owned(),
+                "EVM_END_CALL 2".to_owned(),
+                // Transaction-level summaries have no call index to sort
+                // by; they stay with the end marker rather than defaulting
+                // ahead of the frames.
+                "STORAGE_STATS 1".to_owned(),
+                "PRECOMPILE_GAS_TOTAL 3000".to_owned(),
+                "END_APPLY_TRX 21000".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn fee_cap_analysis_reports_the_margin_over_base_fee() {
+        let (mut tracer, printer) = test_tracer();
+        let base_fee = U256::from(30_000_000_000u64);
+        // A transaction that barely made it in: 1 wei over the base fee.
+        let gas_price = base_fee + U256::from(1);
+        tracer.record_fee_cap_analysis(&base_fee, &gas_price, &U256::from(1));
+
+        assert_eq!(
+            printer.lines(),
+            vec![format!(
+                "DMLOG FEE_CAP_ANALYSIS {:x} {:x} 1 1",
+                base_fee, gas_price
+            )]
+        );
+    }
+
+    #[test]
+    fn gas_floor_is_emitted_only_when_it_binds() {
+        // Calldata-heavy transaction with little execution: the EIP-7623
+        // floor (21000 + 10 per calldata token) exceeds the execution gas,
+        // so the transaction is charged the floor and we report it.
+        let (mut tracer, printer) = test_tracer();
+        tracer.end_apply_trx(26680, Some(26680));
+
+        // Ordinary transfer: the floor does not bind, nothing extra.
+        let (mut plain, plain_printer) = test_tracer();
+        plain.end_apply_trx(21000, None);
+
+        assert_eq!(printer.lines(), vec!["DMLOG END_APPLY_TRX 26680 26680".to_owned()]);
+        assert_eq!(plain_printer.lines(), vec!["DMLOG END_APPLY_TRX 21000".to_owned()]);
+    }
+
+    #[test]
+    fn precompile_refund_is_recorded_directly() {
+        use eth::Address;
+
+        let (mut tracer, printer) = test_tracer();
+        tracer.record_precompile_refund(&Address::from_low_u64_be(0x0b), 400);
+
+        assert_eq!(
+            printer.lines(),
+            vec![format!(
+                "DMLOG PRECOMPILE_REFUND 0 {:x} 400",
+                Address::from_low_u64_be(0x0b)
+            )]
+        );
+    }
+
+    #[test]
+    fn sstore_gas_covers_eip2200_branches() {
+        use eth::Address;
+
+        let zero = H256::zero();
+        let one = H256::from_low_u64_be(1);
+        let two = H256::from_low_u64_be(2);
+
+        // (original, current, new, gas_cost, refund_delta) per EIP-2200.
+        let branches: &[(&H256, &H256, &H256, u64, i64)] = &[
+            (&zero, &zero, &zero, 800, 0),       // no-op write
+            (&zero, &zero, &one, 20000, 0),      // fresh slot set
+            (&one, &one, &zero, 5000, 15000),    // clean slot cleared
+            (&one, &one, &two, 5000, 0),         // clean slot changed
+            (&one, &zero, &one, 800, -15000),    // dirty slot restored from zero
+            (&one, &two, &one, 800, 4200),       // dirty slot reset to original
+        ];
+
+        let (mut tracer, printer) = test_tracer();
+        let address = Address::from_low_u64_be(0xc0de);
+        let key = H256::from_low_u64_be(0x10);
+        for &(original, current, new, gas_cost, refund_delta) in branches {
+            tracer.record_sstore_gas(&address, &key, original, current, new, gas_cost, refund_delta);
+        }
+
+        let lines = printer.lines();
+        assert_eq!(lines.len(), branches.len());
+        for (line, &(_, _, _, gas_cost, refund_delta)) in lines.iter().zip(branches) {
+            assert!(line.starts_with("DMLOG SSTORE_GAS 0 "));
+            assert!(line.ends_with(&format!(" {} {}", gas_cost, refund_delta)));
+        }
+    }
+
+    #[test]
+    fn blob_hashes_are_recorded_individually() {
+        let (mut tracer, printer) = test_tracer();
+        let first = H256::from_low_u64_be(0x0101);
+        let second = H256::from_low_u64_be(0x0202);
+        tracer.record_blob_hash(0, &first);
+        tracer.record_blob_hash(1, &second);
+
+        assert_eq!(
+            printer.lines(),
+            vec![
+                format!("DMLOG BLOB_HASH 0 {:x}", first),
+                format!("DMLOG BLOB_HASH 1 {:x}", second),
+            ]
+        );
+    }
+
+    #[test]
+    fn cold_balance_read_pairs_with_cold_access_gas() {
+        use eth::Address;
+        use gas::GasChangeReason;
+
+        let (mut tracer, printer) = test_tracer();
+        let address = Address::from_low_u64_be(0xabcd);
+        let balance = U256::from(1000);
+
+        tracer.record_balance_read(&address, &balance, true, false);
+        tracer.record_gas_change(100_000, 97_400, GasChangeReason::BalanceColdAccess);
+        tracer.record_balance_read(&address, &balance, false, false);
+
+        let lines = printer.lines();
+        assert!(lines[0].ends_with(" 3e8 true false"));
+        assert_eq!(lines[1], "DMLOG GAS_CHANGE 0 100000 97400 balance_cold_access");
+        assert!(lines[2].ends_with(" 3e8 false false"));
+    }
+
+    #[test]
+    fn selfbalance_is_distinguished_from_balance_of_self() {
+        use eth::Address;
+        use gas::GasChangeReason;
+
+        let (mut tracer, printer) = test_tracer();
+        let own = Address::from_low_u64_be(0xc0de);
+        let balance = U256::from(7);
+
+        // SELFBALANCE: flat 5 gas, never a cold access.
+        tracer.record_balance_read(&own, &balance, false, true);
+        tracer.record_gas_change(100_000, 99_995, GasChangeReason::SelfBalance);
+        // BALANCE of the executing account still pays the warm access cost.
+        tracer.record_balance_read(&own, &balance, false, false);
+
+        let lines = printer.lines();
+        assert!(lines[0].ends_with(" false true"));
+        assert_eq!(lines[1], "DMLOG GAS_CHANGE 0 100000 99995 self_balance");
+        assert!(lines[2].ends_with(" false false"));
+    }
+
+    #[test]
+    fn selfdestruct_to_own_address_is_flagged_as_self_burn() {
+        use eth::Address;
+
+        let (mut tracer, printer) = test_tracer();
+        let contract = Address::from_low_u64_be(0xc0de);
+        tracer.record_selfdestruct(&contract, &contract, &U256::from(100));
+        tracer.record_selfdestruct(&contract, &Address::from_low_u64_be(0xbeef), &U256::from(100));
+
+        let lines = printer.lines();
+        assert!(lines[0].ends_with(" 64 true"));
+        assert!(lines[1].ends_with(" 64 false"));
+    }
+
+    #[test]
+    fn eof_deploy_records_section_layout() {
+        let (mut tracer, printer) = test_tracer();
+        // Synthetic EOF container: magic, version 1, one 2-byte code
+        // section, one 4-byte data section, terminator, then the contents.
+        let container = [
+            0xef, 0x00, 0x01, 0x01, 0x00, 0x02, 0x02, 0x00, 0x04, 0x00, 0x60, 0x00, 0xaa, 0xbb,
+            0xcc, 0xdd,
+        ];
+        tracer.record_eof_deploy(&container);
+
+        assert_eq!(printer.lines(), vec!["DMLOG EOF_DEPLOY 0 1 1 4".to_owned()]);
+    }
+
+    #[test]
+    fn legacy_code_is_not_recorded_as_eof() {
+        let (mut tracer, printer) = test_tracer();
+        tracer.record_eof_deploy(&[0x60, 0x00, 0x60, 0x00]);
+
+        assert!(printer.lines().is_empty());
+    }
+
+    #[test]
+    fn return_data_copy_carries_copied_size() {
+        let (mut tracer, printer) = test_tracer();
+        // Copying a 4096-byte return buffer: 3 base + 3 * 128 words.
+        let size = 4096u64;
+        let gas_cost = 3 + 3 * ((size + 31) / 32);
+        tracer.record_return_data_copy(size, gas_cost);
+
+        assert_eq!(
+            printer.lines(),
+            vec!["DMLOG RETURN_DATA_COPY 0 4096 387".to_owned()]
+        );
+    }
+
+    #[test]
+    fn deployment_pays_code_storage_per_byte() {
+        use eth::Address;
+        use rustc_hex::ToHex;
+
+        let (mut tracer, printer) = test_tracer();
+        let deployed = Address::from_low_u64_be(0xc0de);
+        // A 3-byte runtime (STOP padded) costs 600 gas to store.
+        let code = [0x60u8, 0x00, 0x00];
+        tracer.record_contract_deployed(&deployed, &code, 10_000);
+
+        assert_eq!(
+            printer.lines(),
+            vec![
+                format!("DMLOG CODE_CHANGE 0 {:x} {} 600", deployed, code.to_hex()),
+                "DMLOG GAS_CHANGE 0 10000 9400 deployment_code_storage".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn return_gas_attribution_tells_deployment_from_plain_calls() {
+        use eth::Address;
+
+        let caller = Address::from_low_u64_be(0xaa);
+        let deployed = Address::from_low_u64_be(0xc0de);
+        let code = [0x60u8, 0x00, 0x00];
+
+        // RETURN ending a CREATE frame: the returned bytes are the code to
+        // store, charged under the deployment-specific reason.
+        let (mut tracer, printer) = test_tracer();
+        tracer.start_call(CallKind::Create, &caller, &deployed, &U256::zero(), 50_000, &[]);
+        tracer.record_contract_deployed(&deployed, &code, 10_000);
+        tracer.end_call(9_400, &code);
+        assert!(printer
+            .lines()
+            .iter()
+            .any(|line| line.ends_with("deployment_code_storage")));
+
+        // RETURN ending a plain call: the bytes only become return data,
+        // with no storage cost in any shape.
+        let (mut tracer, printer) = test_tracer();
+        tracer.start_call(CallKind::Call, &caller, &deployed, &U256::zero(), 50_000, &[]);
+        tracer.end_call(40_000, &[0x01]);
+        assert!(!printer
+            .lines()
+            .iter()
+            .any(|line| line.starts_with("DMLOG GAS_CHANGE")));
+    }
+
+    #[test]
+    fn deployment_unable_to_afford_code_storage_fails_clearly() {
+        use eth::Address;
+
+        let (mut tracer, printer) = test_tracer();
+        let deployed = Address::from_low_u64_be(0xc0de);
+        // 100 bytes of code want 20000 gas, the frame has 400 left.
+        tracer.record_contract_deployed(&deployed, &[0u8; 100], 400);
+
+        assert_eq!(
+            printer.lines(),
+            vec![format!(
+                "DMLOG CODE_STORAGE_OUT_OF_GAS 0 {:x} 100 20000 400",
+                deployed
+            )]
+        );
+    }
+
+    #[test]
+    fn creation_transaction_reports_its_derived_contract_address() {
+        use eth::Address;
+
+        let (mut tracer, printer) = test_tracer();
+        begin_trx(&mut tracer, Some(1));
+        tracer.record_from(&Address::zero());
+        tracer.end_apply_trx(53_000, None);
+
+        // The well-known derivation for the zero sender at nonce 0.
+        let expected: Address = "bd770416a3345f91e4b34576cb804a576fa48eb1".parse().unwrap();
+        assert!(printer
+            .lines()
+            .last()
+            .unwrap()
+            .ends_with(&format!(" {:x}", expected)));
+
+        // A plain call reports the absent sentinel instead.
+        let (mut call, call_printer) = test_tracer();
+        call.begin_apply_trx(
+            &H256::from_low_u64_be(2),
+            Some(&Address::from_low_u64_be(0xbb)),
+            &U256::zero(),
+            21_000,
+            &U256::from(1_000_000_000u64),
+            0,
+            &[],
+            Some(1),
+        );
+        call.end_apply_trx(21_000, None);
+        assert!(call_printer.lines().last().unwrap().ends_with(" ."));
+    }
+
+    #[test]
+    fn create_address_encodes_multi_byte_nonces() {
+        use eth::Address;
+        use keccak_hash::keccak;
+
+        // Independent check against an explicitly built RLP preimage:
+        // list header, 20-byte sender, two-byte nonce 0x0400.
+        let mut preimage = vec![0xd8u8, 0x94];
+        preimage.extend_from_slice(&[0u8; 20]);
+        preimage.extend_from_slice(&[0x82, 0x04, 0x00]);
+        let expected = Address::from_slice(&keccak(&preimage).as_bytes()[12..]);
+
+        assert_eq!(create_address(&Address::zero(), 0x0400), expected);
+    }
+
+    #[test]
+    fn gas_limit_ratio_exposes_over_estimated_transactions() {
+        let (mut tracer, printer) = test_tracer();
+        // A transaction declaring 1M gas and using 150k: 1500 bps, a
+        // heavily over-estimated limit.
+        tracer.begin_apply_trx(
+            &H256::from_low_u64_be(1),
+            None,
+            &U256::zero(),
+            1_000_000,
+            &U256::from(1_000_000_000u64),
+            0,
+            &[],
+            Some(1),
+        );
+        tracer.end_apply_trx(150_000, None);
+
+        // Declared limit and the ratio sit next to the used gas, so bundle
+        // analysis needs no join back to BEGIN_APPLY_TRX.
+        assert_eq!(
+            printer.lines().last().unwrap(),
+            "DMLOG END_APPLY_TRX 150000 1000000 1500 ."
+        );
+
+        // Without a begin (e.g. system operations) no ratio is derived.
+        let (mut plain, plain_printer) = test_tracer();
+        plain.end_apply_trx(21_000, None);
+        assert_eq!(plain_printer.lines(), vec!["DMLOG END_APPLY_TRX 21000".to_owned()]);
+    }
+
+    #[test]
+    fn hashing_precompile_gas_follows_the_per_word_formula() {
+        use eth::Address;
+
+        // (precompile, input length, base + per_word * ceil(len / 32)).
+        let cases = [
+            (2u64, 0u64, 60u64),
+            (2, 32, 72),
+            (2, 33, 84),
+            (3, 0, 600),
+            (3, 64, 840),
+            (4, 0, 15),
+            (4, 33, 21),
+        ];
+
+        let (mut tracer, printer) = test_tracer();
+        for &(precompile, length, _) in &cases {
+            tracer.record_hashing_precompile_gas(&Address::from_low_u64_be(precompile), length);
+        }
+        // Not a hashing precompile: nothing is derived or emitted.
+        tracer.record_hashing_precompile_gas(&Address::from_low_u64_be(0x9), 32);
+        tracer.end_apply_trx(100_000, None);
+
+        let lines = printer.lines();
+        let mut total = 0;
+        for (line, &(precompile, length, gas)) in lines.iter().zip(&cases) {
+            assert_eq!(
+                *line,
+                format!(
+                    "DMLOG HASHING_PRECOMPILE_GAS 0 {:x} {} {}",
+                    Address::from_low_u64_be(precompile),
+                    length,
+                    gas
+                )
+            );
+            total += gas;
+        }
+        // The derived costs feed the precompile gas summary.
+        assert_eq!(
+            lines[cases.len()],
+            format!("DMLOG PRECOMPILE_GAS_TOTAL {}", total)
+        );
+    }
+
+    #[test]
+    fn min_value_wei_filters_dust_transfers_only() {
+        use eth::Address;
+        use gas::BalanceChangeReason;
+
+        let address = Address::from_low_u64_be(0xaa);
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            min_value_wei: Some(U256::from(1_000_000u64)),
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let mut tracer = ctx.block_context().transaction_tracer();
+
+        // A 100 wei dust transfer, a significant one, and dust moved by a
+        // non-transfer reason, which the filter never touches.
+        tracer.record_balance_change(
+            &address,
+            &U256::from(1_000u64),
+            &U256::from(1_100u64),
+            BalanceChangeReason::Transfer,
+        );
+        tracer.record_balance_change(
+            &address,
+            &U256::from(1_000u64),
+            &U256::from(2_000_000u64),
+            BalanceChangeReason::Transfer,
+        );
+        tracer.record_balance_change(
+            &address,
+            &U256::from(1_000u64),
+            &U256::from(1_100u64),
+            BalanceChangeReason::GasRefund,
+        );
+
+        let lines = printer.lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            format!("DMLOG BALANCE_CHANGE 0 {:x} 3e8 1e8480 transfer", address)
+        );
+        assert!(lines[1].ends_with("gas_refund"));
+
+        // Without a threshold the dust transfer emits as always.
+        let (mut tracer, printer) = test_tracer();
+        tracer.record_balance_change(
+            &address,
+            &U256::from(1_000u64),
+            &U256::from(1_100u64),
+            BalanceChangeReason::Transfer,
+        );
+        assert_eq!(printer.lines().len(), 1);
+    }
+
+    #[test]
+    fn declared_gas_limit_is_co_located_with_gas_used() {
+        let (mut tracer, printer) = test_tracer();
+        begin_trx(&mut tracer, Some(1));
+        tracer.end_apply_trx(21_000, None);
+
+        // A transfer using its limit exactly: declared 21000, ratio 10000.
+        assert_eq!(
+            printer.lines().last().unwrap(),
+            "DMLOG END_APPLY_TRX 21000 21000 10000 ."
+        );
+    }
+
+    #[test]
+    fn priority_fee_is_capped_by_the_max_fee_margin() {
+        let (mut tracer, printer) = test_tracer();
+        // A 2 gwei tip offer against a max fee only 1 gwei above the base
+        // fee: the margin caps the tip below max_priority_fee.
+        let base_fee = U256::from(30_000_000_000u64);
+        let max_fee = base_fee + U256::from(1_000_000_000u64);
+        let max_priority_fee = U256::from(2_000_000_000u64);
+        tracer.record_priority_fee(&max_priority_fee, &max_fee, &base_fee, 21_000);
+        tracer.end_apply_trx(21_000, None);
+
+        let paid = U256::from(1_000_000_000u64) * U256::from(21_000u64);
+        assert_eq!(
+            printer.lines(),
+            vec![format!("DMLOG END_APPLY_TRX 21000 {:x}", paid)]
+        );
+    }
+
+    #[test]
+    fn fork_tags_name_the_active_fork_on_gas_changes() {
+        use gas::Fork;
+
+        for &(tags, expected) in &[
+            (true, "DMLOG GAS_CHANGE 0 100000 97400 balance_cold_access berlin"),
+            (false, "DMLOG GAS_CHANGE 0 100000 97400 balance_cold_access"),
+        ] {
+            let printer = Arc::new(MemoryPrinter::new());
+            let config = Config {
+                fork_tags: tags,
+                ..Default::default()
+            };
+            let ctx = Context::new(config, printer.clone());
+            let block = ctx.block_context();
+            block.set_active_fork(Fork::Berlin);
+            let mut tracer = block.transaction_tracer();
+            tracer.record_gas_change(100_000, 97_400, GasChangeReason::BalanceColdAccess);
+
+            assert_eq!(printer.lines(), vec![expected.to_owned()]);
+        }
+    }
+
+    #[test]
+    fn storage_root_change_follows_a_storage_write() {
+        use eth::Address;
+
+        let (mut tracer, printer) = test_tracer();
+        let contract = Address::from_low_u64_be(0xc0de);
+        let key = H256::from_low_u64_be(1);
+        // The empty-trie root every fresh account starts from.
+        let old_root: H256 = "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+            .parse()
+            .unwrap();
+        let new_root = H256::from_low_u64_be(0xabcd);
+
+        tracer.record_storage_change(&contract, &key, &H256::zero(), &H256::from_low_u64_be(2));
+        tracer.record_storage_root_change(&contract, &old_root, &new_root);
+
+        assert_eq!(
+            printer.lines()[1],
+            format!(
+                "DMLOG STORAGE_ROOT_CHANGE 0 {:x} {:x} {:x}",
+                contract, old_root, new_root
+            )
+        );
+    }
+
+    #[test]
+    fn gas_opcode_observation_requires_opcode_level_instrumentation() {
+        for &(enabled, expected) in &[(true, 1usize), (false, 0)] {
+            let printer = Arc::new(MemoryPrinter::new());
+            let config = Config {
+                opcode_level: enabled,
+                ..Default::default()
+            };
+            let ctx = Context::new(config, printer.clone());
+            let mut tracer = ctx.block_context().transaction_tracer();
+            // A gas-forwarding contract reading its remaining gas before a
+            // CALL, as in the classic `gas() - reserve` pattern.
+            tracer.record_gas_opcode(63_215);
+
+            let lines = printer.lines();
+            assert_eq!(lines.len(), expected);
+            if enabled {
+                assert_eq!(lines[0], "DMLOG GAS_OPCODE 0 63215");
+            }
+        }
+    }
+
+    #[test]
+    fn blob_base_fee_read_is_an_env_read() {
+        let (mut tracer, printer) = test_tracer();
+        // A blob-posting contract checking the fee before committing.
+        tracer.record_blob_base_fee_read(&U256::from(0x3b9aca00u64));
+
+        assert_eq!(
+            printer.lines(),
+            vec!["DMLOG ENV_READ 0 blob_base_fee 3b9aca00".to_owned()]
+        );
+    }
+
+    #[test]
+    fn blobhash_opcode_records_valid_and_out_of_range_reads() {
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            opcode_level: true,
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let mut tracer = ctx.block_context().transaction_tracer();
+
+        // A single-blob transaction reading index 0, then probing index 1,
+        // which is out of range and pushes zero.
+        let hash = H256::from_low_u64_be(0x0101);
+        tracer.record_blobhash_opcode(0, &hash);
+        tracer.record_blobhash_opcode(1, &H256::zero());
+
+        assert_eq!(
+            printer.lines(),
+            vec![
+                format!("DMLOG BLOBHASH 0 0 {:x}", hash),
+                "DMLOG BLOBHASH 0 1 .".to_owned(),
+            ]
+        );
+
+        // Silent without opcode-level instrumentation.
+        let (mut plain, plain_printer) = test_tracer();
+        plain.record_blobhash_opcode(0, &hash);
+        assert!(plain_printer.lines().is_empty());
+    }
+
+    #[test]
+    fn validation_complete_precedes_the_first_run_call() {
+        use eth::Address;
+
+        let (mut tracer, printer) = test_tracer();
+        begin_trx(&mut tracer, Some(1));
+        // 21000 base plus 680 of calldata gas, charged before execution.
+        tracer.record_validation_complete(21_680);
+        tracer.start_call(
+            CallKind::Call,
+            &Address::from_low_u64_be(0xaa),
+            &Address::from_low_u64_be(0xbb),
+            &U256::zero(),
+            78_320,
+            &[],
+        );
+
+        let lines = printer.lines();
+        assert_eq!(lines[1], "DMLOG VALIDATION_COMPLETE 21680");
+        assert!(lines[2].starts_with("DMLOG EVM_RUN_CALL 1 "));
+    }
+
+    #[test]
+    fn access_list_slots_are_warmed_before_any_sload() {
+        use eth::Address;
+        use gas::Fork;
+
+        let (mut tracer, printer) = test_tracer();
+        let contract = Address::from_low_u64_be(0xc0de);
+        let other = Address::from_low_u64_be(0xbeef);
+        let (k1, k2) = (H256::from_low_u64_be(1), H256::from_low_u64_be(2));
+
+        // A type-2 transaction declaring three slots across two accounts,
+        // then reading one of them: the read is warm from the start.
+        let access_list = vec![(contract, vec![k1, k2]), (other, vec![k1])];
+        tracer.record_access_list(&access_list);
+        tracer.record_sload_gas(&contract, &k1, Fork::Berlin, false);
+
+        let lines = printer.lines();
+        assert_eq!(
+            &lines[..3],
+            &[
+                format!("DMLOG SLOT_WARMED {:x} {:x}", contract, k1),
+                format!("DMLOG SLOT_WARMED {:x} {:x}", contract, k2),
+                format!("DMLOG SLOT_WARMED {:x} {:x}", other, k1),
+            ]
+        );
+        assert!(lines[3].starts_with("DMLOG SLOAD_GAS "));
+        assert!(lines[3].ends_with(" berlin false 100"));
+    }
+
+    #[test]
+    fn debug_source_locations_tag_the_call_site() {
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            debug_source_locations: true,
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let mut tracer = ctx.block_context().transaction_tracer();
+        let call_site = line!() + 1;
+        tracer.record_code_analysis(24576, 1800);
+
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Debug),
+            vec![format!("CODE_ANALYSIS 0 24576 1800 {}:{}", file!(), call_site)]
+        );
+
+        // An invariant warning emitted from inside a recorder tags the
+        // embedder's call site, not the recorder's own `emit_debug` line.
+        let underflow_site = line!() + 1;
+        tracer.record_selfdestruct_gas(400, Fork::London, false);
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Debug)[1],
+            format!(
+                "SELFDESTRUCT_GAS_UNDERFLOW 0 400 5000 self_destruct {}:{}",
+                file!(),
+                underflow_site
+            )
+        );
+    }
+
+    #[test]
+    fn selfdestruct_gas_is_fork_accurate() {
+        use gas::Fork;
+
+        // The same SELFDESTRUCT replayed under three schedules: free at
+        // launch, 5000 from EIP-150, plus the separated 2600 cold-
+        // beneficiary surcharge from Berlin.
+        let cases: &[(Fork, bool, &[&str])] = &[
+            (Fork::Frontier, true, &[]),
+            (Fork::Tangerine, true, &["DMLOG GAS_CHANGE 0 100000 95000 self_destruct"]),
+            (
+                Fork::Berlin,
+                true,
+                &[
+                    "DMLOG GAS_CHANGE 0 100000 95000 self_destruct",
+                    "DMLOG GAS_CHANGE 0 95000 92400 cold_beneficiary_access",
+                ],
+            ),
+            (
+                Fork::Berlin,
+                false,
+                &["DMLOG GAS_CHANGE 0 100000 95000 self_destruct"],
+            ),
+        ];
+
+        for &(fork, cold, expected) in cases {
+            let (mut tracer, printer) = test_tracer();
+            tracer.record_selfdestruct_gas(100_000, fork, cold);
+            let expected: Vec<String> = expected.iter().map(|line| line.to_string()).collect();
+            assert_eq!(printer.lines(), expected, "under {:?}", fork);
+        }
+    }
+
+    #[test]
+    fn selfdestruct_gas_underflow_is_reported_off_stream() {
+        use gas::Fork;
+
+        // A frame with less gas than the 5000 base: an embedder bug, not a
+        // legal metering, so nothing reaches the protocol stream.
+        let (mut tracer, printer) = test_tracer();
+        tracer.record_selfdestruct_gas(400, Fork::London, false);
+        assert!(printer.lines_on(::printer::Channel::Log).is_empty());
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Debug),
+            vec!["SELFDESTRUCT_GAS_UNDERFLOW 0 400 5000 self_destruct".to_owned()]
+        );
+
+        // Enough for the base but not the cold surcharge: the base is
+        // recorded, the surcharge is reported instead of wrapping.
+        let (mut tracer, printer) = test_tracer();
+        tracer.record_selfdestruct_gas(6_000, Fork::Berlin, true);
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Log),
+            vec!["GAS_CHANGE 0 6000 1000 self_destruct".to_owned()]
+        );
+        assert_eq!(
+            printer.lines_on(::printer::Channel::Debug),
+            vec![
+                "SELFDESTRUCT_GAS_UNDERFLOW 0 1000 2600 cold_beneficiary_access".to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn sload_gas_is_fork_accurate() {
+        use eth::Address;
+        use gas::Fork;
+
+        let address = Address::from_low_u64_be(0xc0de);
+        let key = H256::from_low_u64_be(1);
+
+        // The same SLOAD replayed under four schedules: 200 from EIP-150,
+        // 800 from EIP-1884, 2100 cold / 100 warm from EIP-2929. Cold is
+        // ignored before Berlin.
+        let cases = [
+            (Fork::Tangerine, true, "tangerine true 200"),
+            (Fork::Istanbul, true, "istanbul true 800"),
+            (Fork::Berlin, true, "berlin true 2100"),
+            (Fork::Berlin, false, "berlin false 100"),
+        ];
+
+        let (mut tracer, printer) = test_tracer();
+        for &(fork, cold, _) in &cases {
+            tracer.record_sload_gas(&address, &key, fork, cold);
+        }
+
+        let lines = printer.lines();
+        for (line, &(_, _, suffix)) in lines.iter().zip(&cases) {
+            assert_eq!(
+                *line,
+                format!("DMLOG SLOAD_GAS 0 {:x} {:x} {}", address, key, suffix)
+            );
+        }
+
+        // Launch pricing, for completeness of the schedule.
+        assert_eq!(Fork::Frontier.sload_cost(true), 50);
+
+        // The read still counts towards the storage statistics.
+        tracer.end_apply_trx(30_000, None);
+        assert_eq!(
+            printer.lines()[cases.len()],
+            "DMLOG STORAGE_STATS 1 0 1"
+        );
+    }
+
+    #[test]
+    fn call_trace_matches_the_geth_call_tracer_shape() {
+        use eth::Address;
+
+        let a = Address::from_low_u64_be(0xaa);
+        let b = Address::from_low_u64_be(0xbb);
+        let c = Address::from_low_u64_be(0xcc);
+
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            call_tracer: true,
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let mut tracer = ctx.block_context().transaction_tracer();
+
+        // A calls B with value, B static-calls C, both frames complete.
+        tracer.start_call(CallKind::Call, &a, &b, &U256::from(0x1000), 100_000, &[0xab, 0xcd]);
+        tracer.start_call(CallKind::StaticCall, &b, &c, &U256::zero(), 50_000, &[]);
+        tracer.end_call(40_000, &[0x01]);
+        tracer.end_call(60_000, &[]);
+        tracer.end_apply_trx(40_000, None);
+
+        // What Geth's callTracer emits for the same execution: empty
+        // `output` and childless `calls` are omitted, quantities are
+        // minimal hex.
+        let expected = format!(
+            "DMLOG CALL_TRACE {{\"type\":\"CALL\",\"from\":\"0x{:x}\",\"to\":\"0x{:x}\",\
+             \"value\":\"0x1000\",\"gas\":\"0x186a0\",\"gasUsed\":\"0x9c40\",\"input\":\"0xabcd\",\
+             \"calls\":[{{\"type\":\"STATICCALL\",\"from\":\"0x{:x}\",\"to\":\"0x{:x}\",\
+             \"value\":\"0x0\",\"gas\":\"0xc350\",\"gasUsed\":\"0x2710\",\"input\":\"0x\",\
+             \"output\":\"0x01\"}}]}}",
+            a, b, b, c
+        );
+        let lines = printer.lines();
+        // The trace precedes END_APPLY_TRX: two frame events each, then the
+        // tree, then the end marker.
+        assert_eq!(lines[4], expected);
+        assert!(lines[5].starts_with("DMLOG END_APPLY_TRX "));
+
+        // Off by default: the same execution leaves no trace line.
+        let (mut tracer, printer) = test_tracer();
+        tracer.start_call(CallKind::Call, &a, &b, &U256::zero(), 100_000, &[]);
+        tracer.end_call(60_000, &[]);
+        tracer.end_apply_trx(40_000, None);
+        assert!(!printer.lines().iter().any(|line| line.contains("CALL_TRACE")));
+    }
+
+    #[test]
+    fn call_trace_waits_for_the_sorted_flush() {
+        use eth::Address;
+
+        let a = Address::from_low_u64_be(0xaa);
+        let b = Address::from_low_u64_be(0xbb);
+        let c = Address::from_low_u64_be(0xcc);
+
+        let printer = Arc::new(MemoryPrinter::new());
+        let config = Config {
+            call_tracer: true,
+            sort_by_call_index: true,
+            ..Default::default()
+        };
+        let ctx = Context::new(config, printer.clone());
+        let mut tracer = ctx.block_context().transaction_tracer();
+
+        tracer.start_call(CallKind::Call, &a, &b, &U256::zero(), 100_000, &[]);
+        tracer.start_call(CallKind::StaticCall, &b, &c, &U256::zero(), 50_000, &[]);
+        tracer.end_call(40_000, &[]);
+        tracer.end_call(60_000, &[]);
+        tracer.end_apply_trx(40_000, None);
+
+        // The whole transaction was buffered for sorting, yet the trace
+        // still lands directly ahead of END_APPLY_TRX — not before the
+        // events it summarises.
+        let lines = printer.lines();
+        assert!(lines[0].starts_with("DMLOG EVM_RUN_CALL "));
+        assert!(lines[lines.len() - 2].starts_with("DMLOG CALL_TRACE {\"type\":\"CALL\""));
+        assert!(lines[lines.len() - 1].starts_with("DMLOG END_APPLY_TRX "));
+    }
+
+    #[test]
+    fn pre_eip155_transaction_has_no_chain_id() {
+        let (mut tracer, printer) = test_tracer();
+        begin_trx(&mut tracer, None);
+
+        let lines = printer.lines();
+        assert_eq!(*lines[0].split(' ').collect::<Vec<_>>().last().unwrap(), ".");
+    }
+}
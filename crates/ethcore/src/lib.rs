@@ -21,6 +21,7 @@
 extern crate ansi_term;
 extern crate common_types as types;
 extern crate crossbeam_utils;
+extern crate deepmind;
 extern crate derive_more;
 extern crate ethabi;
 extern crate ethash;